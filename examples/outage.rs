@@ -0,0 +1,55 @@
+//! Checks that a breaker configuration recovers quickly after an
+//! outage of its dependency. Run with:
+//!
+//! ```text
+//! cargo run --example outage --features sim
+//! ```
+
+extern crate crius;
+
+use crius::circuit_breaker::CircuitState;
+use crius::command::Config;
+use crius::sim::{SimulatedDependency, Simulation};
+use std::time::Duration;
+
+fn main() {
+    // Healthy for 30s, down for 20s, then healthy again:
+    let outage_start = Duration::from_secs(30);
+    let outage_end = Duration::from_secs(50);
+    let mut dependency = SimulatedDependency::new();
+    dependency
+        .phase(outage_start, 0.01, Duration::from_millis(20))
+        .phase(outage_end - outage_start, 1.0, Duration::from_millis(500))
+        .phase(Duration::from_secs(40), 0.01, Duration::from_millis(20));
+
+    let config = *Config::default()
+        .error_threshold(10)
+        .error_threshold_percentage(50)
+        .circuit_open_ms(5000);
+    let report = Simulation::new(dependency)
+        .run(config, Duration::from_secs(90))
+        .expect("Invalid configuration");
+
+    for &(time, state) in &report.transitions {
+        println!("{:>8.1}s {:?}", time.as_secs_f64(), state);
+    }
+    println!(
+        "{} successes, {} failures, {} rejections",
+        report.successes, report.failures, report.rejections
+    );
+
+    assert!(report.opened_between(outage_start, outage_end));
+    assert_eq!(
+        CircuitState::Closed,
+        report.state_at(Duration::from_secs(90))
+    );
+
+    // The breaker recovers within one open duration after the outage:
+    let recovered_at = report.closed_since().expect("Breaker never recovered");
+    let recovery = recovered_at.saturating_sub(outage_end);
+    println!(
+        "Recovered {:.1}s after the outage ended",
+        recovery.as_secs_f64()
+    );
+    assert!(recovery <= Duration::from_millis(config.circuit_open_ms));
+}
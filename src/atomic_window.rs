@@ -0,0 +1,182 @@
+use clock::{Clock, SystemClock};
+use command::Config;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use window::Point;
+
+/// A single counter of a bucket. The upper 32 bits hold the number of
+/// the bucket period (tick) the count belongs to, the lower 32 bits
+/// hold the count itself. Keeping both in one atomic lets a counter be
+/// rotated into a new period and incremented with a single CAS.
+#[derive(Debug)]
+struct Counter(AtomicU64);
+
+impl Counter {
+    fn new() -> Counter {
+        Counter(AtomicU64::new(0))
+    }
+
+    fn increment(&self, tick: u32) {
+        let mut current = self.0.load(Ordering::Acquire);
+        loop {
+            let (current_tick, count) = unpack(current);
+            let new = if current_tick == tick {
+                pack(tick, count.saturating_add(1))
+            } else {
+                pack(tick, 1)
+            };
+
+            match self
+                .0
+                .compare_exchange_weak(current, new, Ordering::AcqRel, Ordering::Acquire)
+            {
+                Ok(_) => return,
+                Err(actual) => current = actual,
+            }
+        }
+    }
+
+    /// Returns the count if it belongs to one of the `buckets_nr`
+    /// periods up to and including `tick`.
+    fn valid_count(&self, tick: u32, buckets_nr: u32) -> u32 {
+        let (counter_tick, count) = unpack(self.0.load(Ordering::Acquire));
+        if tick.wrapping_sub(counter_tick) < buckets_nr {
+            count
+        } else {
+            0
+        }
+    }
+
+    fn clear(&self) {
+        self.0.store(0, Ordering::Release);
+    }
+}
+
+fn pack(tick: u32, count: u32) -> u64 {
+    (u64::from(tick) << 32) | u64::from(count)
+}
+
+fn unpack(value: u64) -> (u32, u32) {
+    ((value >> 32) as u32, value as u32)
+}
+
+#[derive(Debug)]
+struct AtomicBucket {
+    successes: Counter,
+    failures: Counter,
+}
+
+/// A window tracking successes and failures with atomic counters
+/// instead of point vectors. Points can be added and counted through a
+/// shared reference without locking, which makes this window suitable
+/// for breakers shared between many threads.
+///
+/// The buckets form a ring that is indexed by the number of bucket
+/// periods elapsed since the window was created. A bucket is rotated
+/// lazily when the first point of a new period is added to it.
+#[derive(Debug)]
+pub struct AtomicWindow {
+    /// Ring of buckets, one per bucket period in the window
+    buckets: Vec<AtomicBucket>,
+
+    /// Period during which a single bucket is valid
+    bucket_ms: Duration,
+
+    /// Time the first bucket period started at
+    start: Instant,
+
+    clock: Arc<dyn Clock>,
+}
+
+impl AtomicWindow {
+    /// Attempt to create a window from a given configuration. Returns
+    /// `None` if the window would not contain any buckets or if the
+    /// duration calculations overflowed.
+    pub fn new(config: Config) -> Option<Self> {
+        AtomicWindow::with_clock(config, Arc::new(SystemClock))
+    }
+
+    /// Like `new`, but reads the current time from the given clock.
+    pub fn with_clock(config: Config, clock: Arc<dyn Clock>) -> Option<Self> {
+        let bucket_ms = Duration::from_millis(config.bucket_size_in_ms);
+        if config.buckets_in_window == 0 || config.bucket_size_in_ms == 0 {
+            return None;
+        }
+
+        bucket_ms.checked_mul(config.buckets_in_window)?;
+        let buckets = (0..config.buckets_in_window)
+            .map(|_| AtomicBucket {
+                successes: Counter::new(),
+                failures: Counter::new(),
+            })
+            .collect();
+
+        Some(AtomicWindow {
+            buckets,
+            bucket_ms,
+            start: clock.now(),
+            clock,
+        })
+    }
+
+    /// Read the current time from the given clock from now on. As
+    /// buckets are indexed by the time elapsed on the clock, this
+    /// clears the window and restarts it at the clock's current time.
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clear_window();
+        self.start = clock.now();
+        self.clock = clock;
+    }
+
+    pub fn add_point(&self, point: Point) {
+        let tick = self.current_tick();
+        let bucket = &self.buckets[tick as usize % self.buckets.len()];
+        match point {
+            Point::SUCCESS => bucket.successes.increment(tick),
+            Point::FAILURE => bucket.failures.increment(tick),
+        }
+    }
+
+    /// Returns the number of successes in all currently valid
+    /// buckets:
+    pub fn success_nr(&self) -> u32 {
+        let tick = self.current_tick();
+        self.buckets
+            .iter()
+            .map(|bucket| bucket.successes.valid_count(tick, self.buckets_nr()))
+            .sum()
+    }
+
+    /// Returns the number of failures in all currently valid buckets:
+    pub fn error_nr(&self) -> u32 {
+        let tick = self.current_tick();
+        self.buckets
+            .iter()
+            .map(|bucket| bucket.failures.valid_count(tick, self.buckets_nr()))
+            .sum()
+    }
+
+    pub fn clear_window(&self) {
+        for bucket in &self.buckets {
+            bucket.successes.clear();
+            bucket.failures.clear();
+        }
+    }
+
+    fn buckets_nr(&self) -> u32 {
+        self.buckets.len() as u32
+    }
+
+    /// Number of bucket periods elapsed since the window was created.
+    /// Wraps around, which is harmless as long as the window is
+    /// shorter than 2^32 bucket periods.
+    fn current_tick(&self) -> u32 {
+        let elapsed = self
+            .clock
+            .now()
+            .saturating_duration_since(self.start)
+            .as_millis();
+        (elapsed / self.bucket_ms.as_millis()) as u32
+    }
+}
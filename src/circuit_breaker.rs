@@ -1,14 +1,134 @@
 use circuit_breaker_stats::CircuitBreakerStats;
-use command::Config;
+use clock::{Clock, ManualClock, SystemClock};
+use command::{BreakerMode, Config, ConfigWarning, OperationClass};
+use coordination::CoordinationSink;
 use error::CriusError;
+use latency_histogram::LatencyHistogram;
+use rng::{Rng, XorShiftRng};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 use window::Point;
 use window::Window;
 
+/// Maximum number of latencies in the window the latency guard's
+/// percentile is computed from. Older latencies are dropped first.
+const MAX_LATENCY_SAMPLES: usize = 1024;
+
+/// The state of a circuit breaker. Calls are rejected while the
+/// breaker is open.
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum CircuitState {
+    Closed,
+    Open,
+}
+
+/// A snapshot of a circuit breaker's statistics.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Metrics {
+    /// Number of successful calls in the current window
+    pub success_nr: i32,
+
+    /// Number of failed calls in the current window
+    pub error_nr: i32,
+
+    /// Percentage of failed calls in the current window
+    pub error_percentage: i32,
+
+    /// State of the breaker
+    pub state: CircuitState,
+}
+
 #[derive(Clone, Debug)]
 pub struct CircuitBreaker {
     circuit_breaker_stats: CircuitBreakerStats,
+
+    /// Outcomes as seen by callers, i.e. after fallbacks were applied.
+    /// These are only reported and never influence the breaker.
+    user_visible_stats: CircuitBreakerStats,
+
+    /// Admission decisions, with rejected calls recorded as failures.
+    /// These are only reported and never influence the breaker.
+    admission_stats: CircuitBreakerStats,
+
+    /// Longer window the metrics are drawn from, if configured. Unlike
+    /// the tripping window, it is not cleared when the circuit opens.
+    reporting_stats: Option<CircuitBreakerStats>,
+
+    /// Outcomes recorded with a tag, grouped by tag. Only reported and
+    /// never influence the breaker.
+    tagged_stats: HashMap<&'static str, CircuitBreakerStats>,
     circuit_open_time: Option<Instant>,
+
+    /// How long the circuit stays open once opened. Only changes if
+    /// `adaptive_open_duration` is set.
+    open_ms: u64,
+
+    /// How long the circuit stays open this time, i.e. the open
+    /// duration raised to `open_duration_floor_ms` if necessary
+    effective_open: Duration,
+
+    /// Whether the next recorded result is the first one after the
+    /// circuit closed again, i.e. a probe of the recovery
+    probing: bool,
+
+    /// Times of the most recent failures, if a burst threshold is
+    /// configured
+    recent_failures: VecDeque<Instant>,
+
+    /// Number of failures since the last success, used in
+    /// `BreakerMode::ConsecutiveFailures`
+    consecutive_failures: u32,
+
+    /// Times the circuit closed again within the probe budget's
+    /// period, if a probe budget is configured
+    recent_probes: VecDeque<Instant>,
+
+    /// Times the fallback was used for rejected calls within the
+    /// fallback rate limit's period, if a limit is configured
+    recent_fallbacks: VecDeque<Instant>,
+
+    /// Start of the recovery ramp, if the circuit closed again less
+    /// than the ramp's total duration ago, see `recovery_ramp`
+    recovery_start: Option<Instant>,
+
+    /// Number of calls allowed since the breaker was created or reset,
+    /// counted against `max_total_calls`
+    total_calls: u64,
+
+    /// Window shared with other breakers, if any. Outcomes are also
+    /// recorded here, and it replaces the own window in decisions to
+    /// open the circuit.
+    shared_window: Option<Arc<Mutex<Window>>>,
+
+    /// Latencies of all calls since the breaker was created
+    latency_histogram: LatencyHistogram,
+
+    /// Latencies of the most recent calls within the window and when
+    /// they were recorded, if a latency guard is configured. Holds at
+    /// most `MAX_LATENCY_SAMPLES` latencies.
+    recent_latencies: VecDeque<(Instant, Duration)>,
+
+    /// Buffer the percentile of `recent_latencies` is selected in,
+    /// reused to avoid allocating on every admission
+    latency_scratch: Vec<Duration>,
+
+    /// Metrics last computed by `metrics_cached` and when
+    cached_metrics: MetricsCache,
+    clock: Arc<dyn Clock>,
+
+    /// Start of the warmup period, see `warmup_ms`
+    warmup_start: Instant,
+    rng: Arc<dyn Rng>,
+    coordination: Option<Arc<dyn CoordinationSink>>,
+
+    /// Whether the circuit is open because the coordinator forced it
+    /// open, rather than due to the local statistics
+    forced_open: bool,
+
+    /// Warnings about the configuration found when the breaker was
+    /// created
+    config_warnings: Vec<ConfigWarning>,
     pub(crate) config: Config,
 }
 
@@ -17,40 +137,636 @@ impl CircuitBreaker {
     /// configuration. This may return `None` if the configuration is
     /// invalid (e.g. if the configured durations overflow).
     pub fn new(config: Config) -> Result<CircuitBreaker, CriusError> {
-        Window::new(config)
-            .map(|window| CircuitBreaker {
-                circuit_breaker_stats: CircuitBreakerStats { window },
-                circuit_open_time: None,
-                config,
+        CircuitBreaker::with_clock(config, Arc::new(SystemClock))
+    }
+
+    /// Like `new`, but reads the current time from the given clock.
+    pub fn with_clock(config: Config, clock: Arc<dyn Clock>) -> Result<CircuitBreaker, CriusError> {
+        let config_warnings = config.validate()?;
+        let window = Window::with_clock(config, clock.clone()).ok_or(CriusError::InvalidConfig)?;
+
+        let reporting_stats = match config.reporting_buckets_in_window {
+            Some(buckets_in_window) => {
+                let mut reporting_config = config;
+                reporting_config.buckets_in_window = buckets_in_window;
+                let window = Window::with_clock(reporting_config, clock.clone())
+                    .ok_or(CriusError::InvalidConfig)?;
+                Some(CircuitBreakerStats { window })
+            }
+            None => None,
+        };
+
+        Ok(CircuitBreaker {
+            user_visible_stats: CircuitBreakerStats {
+                window: window.clone(),
+            },
+            admission_stats: CircuitBreakerStats {
+                window: window.clone(),
+            },
+            circuit_breaker_stats: CircuitBreakerStats { window },
+            reporting_stats,
+            tagged_stats: HashMap::new(),
+            circuit_open_time: None,
+            open_ms: initial_open_ms(&config),
+            effective_open: Duration::from_millis(config.circuit_open_ms),
+            probing: false,
+            forced_open: false,
+            recent_failures: VecDeque::new(),
+            consecutive_failures: 0,
+            recent_probes: VecDeque::new(),
+            recent_fallbacks: VecDeque::new(),
+            recovery_start: None,
+            total_calls: 0,
+            shared_window: None,
+            latency_histogram: LatencyHistogram::new(),
+            recent_latencies: VecDeque::new(),
+            latency_scratch: Vec::new(),
+            cached_metrics: MetricsCache::default(),
+            warmup_start: clock.now(),
+            clock,
+            rng: Arc::new(XorShiftRng::new()),
+            coordination: None,
+            config_warnings,
+            config,
+        })
+    }
+
+    /// Read the current time from the given clock. This restarts the
+    /// warmup period, if any, at the clock's current time.
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.circuit_breaker_stats.window.set_clock(clock.clone());
+        self.user_visible_stats.window.set_clock(clock.clone());
+        self.admission_stats.window.set_clock(clock.clone());
+        if let Some(ref mut reporting_stats) = self.reporting_stats {
+            reporting_stats.window.set_clock(clock.clone());
+        }
+        for tagged_stats in self.tagged_stats.values_mut() {
+            tagged_stats.window.set_clock(clock.clone());
+        }
+        self.warmup_start = clock.now();
+        self.clock = clock;
+    }
+
+    pub fn set_rng(&mut self, rng: Arc<dyn Rng>) {
+        self.rng = rng;
+    }
+
+    /// Consult the given sink before every call, letting an external
+    /// coordinator override the breaker's local decisions.
+    pub fn set_coordination(&mut self, coordination: Arc<dyn CoordinationSink>) {
+        self.coordination = Some(coordination);
+    }
+
+    /// Record outcomes into the given window as well, and decide
+    /// whether to open the circuit based on the outcomes recorded
+    /// there. Breakers sharing a window (e.g. all breakers of calls to
+    /// one host) thus open based on their combined outcomes.
+    ///
+    /// The shared window reads the time from its own clock. Unlike the
+    /// own window, it is not cleared when the circuit opens, so that
+    /// all breakers sharing it open. Until the failures leave the
+    /// shared window, a circuit opens again whenever its open duration
+    /// elapses.
+    pub fn set_shared_window(&mut self, window: Arc<Mutex<Window>>) {
+        self.shared_window = Some(window);
+    }
+
+    pub fn metrics(&mut self) -> Metrics {
+        Metrics {
+            success_nr: self.reporting_stats().success_nr(),
+            error_nr: self.reporting_stats().error_nr(),
+            error_percentage: self.reporting_stats().error_percentage(),
+            state: self.state(),
+        }
+    }
+
+    /// Metrics of the outcomes recorded with each tag, see
+    /// `register_tagged_result`. The state is the breaker's state.
+    pub fn metrics_by_tag(&mut self) -> HashMap<&'static str, Metrics> {
+        let state = self.state();
+        self.tagged_stats
+            .iter_mut()
+            .map(|(&tag, stats)| {
+                let metrics = Metrics {
+                    success_nr: stats.success_nr(),
+                    error_nr: stats.error_nr(),
+                    error_percentage: stats.error_percentage(),
+                    state,
+                };
+                (tag, metrics)
             })
-            .ok_or(CriusError::InvalidConfig)
+            .collect()
+    }
+
+    /// Like `metrics`, but returns the metrics computed by a previous
+    /// call if they are at most `max_staleness` old. Neither call
+    /// prunes the window, so frequent scrapes don't mutate the
+    /// breaker.
+    pub fn metrics_cached(&self, max_staleness: Duration) -> Metrics {
+        let now = self.clock.now();
+        let mut cached_metrics = self.cached_metrics.0.lock().unwrap();
+        if let Some((computed_at, metrics)) = *cached_metrics {
+            if now.saturating_duration_since(computed_at) <= max_staleness {
+                return metrics;
+            }
+        }
+
+        let metrics = self.compute_metrics();
+        *cached_metrics = Some((now, metrics));
+        metrics
+    }
+
+    /// Computes the metrics of the reporting window in a single pass
+    /// over a snapshot of it, without pruning the window.
+    fn compute_metrics(&self) -> Metrics {
+        let stats = self
+            .reporting_stats
+            .as_ref()
+            .unwrap_or(&self.circuit_breaker_stats);
+        let (success_nr, error_nr) =
+            stats
+                .window
+                .snapshot()
+                .points()
+                .fold((0, 0), |(success_nr, error_nr), point| match point {
+                    Point::SUCCESS => (success_nr + 1, error_nr),
+                    Point::FAILURE => (success_nr, error_nr + 1),
+                });
+        let total_nr = success_nr + error_nr;
+        Metrics {
+            success_nr,
+            error_nr,
+            error_percentage: if total_nr == 0 {
+                0
+            } else {
+                error_nr * 100 / total_nr
+            },
+            state: self.state(),
+        }
     }
 
+    pub fn state(&self) -> CircuitState {
+        if self.circuit_open_time.is_some() {
+            CircuitState::Open
+        } else {
+            CircuitState::Closed
+        }
+    }
+
+    /// Close the circuit and start over with empty windows, e.g.
+    /// after fixing the cause of failures. This is the only way to
+    /// close a circuit if `latch_open` is set or once `max_total_calls`
+    /// were served, and starts counting total calls over. The breaker
+    /// behaves as if it was just created, including the adaptive open
+    /// duration and the probe and fallback budgets.
+    ///
+    /// Only the latency histogram, which covers the whole lifetime of
+    /// the breaker, and a window shared with other breakers survive a
+    /// reset.
+    pub fn reset(&mut self) {
+        self.circuit_open_time = None;
+        self.circuit_breaker_stats.clear();
+        self.user_visible_stats.clear();
+        self.admission_stats.clear();
+        if let Some(ref mut reporting_stats) = self.reporting_stats {
+            reporting_stats.clear();
+        }
+        for tagged_stats in self.tagged_stats.values_mut() {
+            tagged_stats.clear();
+        }
+        *self.cached_metrics.0.lock().unwrap() = None;
+        self.open_ms = initial_open_ms(&self.config);
+        self.effective_open = Duration::from_millis(self.config.circuit_open_ms);
+        self.consecutive_failures = 0;
+        self.recent_failures.clear();
+        self.recent_probes.clear();
+        self.recent_fallbacks.clear();
+        self.recent_latencies.clear();
+        self.probing = false;
+        self.forced_open = false;
+        self.recovery_start = None;
+        self.total_calls = 0;
+    }
+
+    /// Decides whether a call is allowed, opening or closing the
+    /// circuit as needed.
+    ///
+    /// Once the open duration elapsed, the circuit closes only if the
+    /// window no longer warrants opening it. Otherwise it opens again
+    /// right away for another open duration, and the call is rejected.
     pub fn check_command_allowed(&mut self) -> bool {
+        self.admit(None)
+    }
+
+    /// Like `check_command_allowed`, but also rejects calls of the
+    /// given class while the error percentage reaches the class's
+    /// threshold, without opening the circuit. See
+    /// `Config::write_error_threshold_percentage`.
+    pub fn check_class_allowed(&mut self, class: OperationClass) -> bool {
+        self.admit(Some(class))
+    }
+
+    fn admit(&mut self, class: Option<OperationClass>) -> bool {
+        // Once the breaker served `max_total_calls`, it stays open
+        // until it is reset:
+        if let Some(max_total_calls) = self.config.max_total_calls {
+            if self.total_calls >= max_total_calls {
+                if self.circuit_open_time.is_none() {
+                    self.open_circuit();
+                }
+                self.admission_stats.add_point(Point::FAILURE);
+                return false;
+            }
+        }
+
+        let is_allowed = self.is_command_allowed() && !self.should_shed_class(class);
+        if is_allowed {
+            self.total_calls = self.total_calls.saturating_add(1);
+            self.admission_stats.add_point(Point::SUCCESS);
+        } else {
+            self.admission_stats.add_point(Point::FAILURE);
+        }
+        is_allowed
+    }
+
+    fn is_command_allowed(&mut self) -> bool {
+        if let Some(coordination) = self.coordination.clone() {
+            coordination.report(self.compute_metrics());
+
+            // The coordinator's decision drives the circuit, so the
+            // state and observers of the breaker follow it. Once the
+            // coordinator stops overriding, a circuit it forced open
+            // closes and the breaker decides locally again:
+            match coordination.fleet_should_open() {
+                Some(true) => {
+                    if self.circuit_open_time.is_none() {
+                        self.circuit_open_time = Some(self.clock.now());
+                        self.forced_open = true;
+                    }
+                    return false;
+                }
+                Some(false) => {
+                    self.circuit_open_time = None;
+                    self.forced_open = false;
+                    return true;
+                }
+                None if self.forced_open => {
+                    self.circuit_open_time = None;
+                    self.forced_open = false;
+                }
+                None => (),
+            }
+        }
+
         if self.should_close_open_circuit() {
+            // Closing is re-evaluated against the window first. A window
+            // that still warrants opening (e.g. due to failures of
+            // bypassing calls or of other breakers sharing the window)
+            // opens the circuit again right away:
+            if self.should_open_circuit() {
+                self.open_circuit();
+                return false;
+            }
+
+            // Stay open until the probe budget allows another probe:
+            if !self.take_probe() {
+                return false;
+            }
             self.circuit_open_time = None;
+            self.probing = self.config.adaptive_open_duration;
+            if self.config.recovery_ramp.is_some() {
+                self.recovery_start = Some(self.clock.now());
+            }
             true
         } else if self.should_keep_circuit_open() {
             false
         } else if self.should_open_circuit() {
-            self.circuit_open_time = Some(Instant::now());
-            self.circuit_breaker_stats.clear();
+            self.open_circuit();
             false
+        } else if self.latency_exceeds_guard() {
+            // Reject calls without opening the circuit, until slow
+            // calls leave the window:
+            false
+        } else if let Some(fraction) = self.recovery_fraction() {
+            // Admit the share of calls of the current recovery stage:
+            self.rng.next_f64() < fraction
+        } else if self.config.graduated_shedding {
+            // Shed a share of the calls matching the error rate:
+            let error_rate = self.circuit_breaker_stats.error_rate();
+            self.rng.next_f64() >= error_rate
         } else {
             true
         }
     }
 
+    /// Records the latency of a call in the latency histogram. The
+    /// latencies in the window are only kept if a latency guard is
+    /// configured.
+    pub fn register_latency(&mut self, latency: Duration) {
+        self.latency_histogram.record(latency);
+        if self.config.latency_trend_guard.is_none() {
+            return;
+        }
+
+        self.prune_latencies();
+        if self.recent_latencies.len() >= MAX_LATENCY_SAMPLES {
+            self.recent_latencies.pop_front();
+        }
+        self.recent_latencies.push_back((self.clock.now(), latency));
+    }
+
+    /// Histogram of the latencies of all calls since the breaker was
+    /// created, see `register_latency`.
+    pub fn latency_histogram(&self) -> &LatencyHistogram {
+        &self.latency_histogram
+    }
+
+    /// Returns the given percentile (between 0.0 and 1.0) of the
+    /// latencies in the window, or `None` if no latencies are recorded.
+    /// Only the `MAX_LATENCY_SAMPLES` (1024) most recent latencies are
+    /// considered, so this takes linear time in at most that many.
+    pub fn latency_percentile(&mut self, p: f64) -> Option<Duration> {
+        self.prune_latencies();
+        if self.recent_latencies.is_empty() {
+            return None;
+        }
+
+        let latencies = &mut self.latency_scratch;
+        latencies.clear();
+        latencies.extend(self.recent_latencies.iter().map(|&(_, latency)| latency));
+        let rank = (p * latencies.len() as f64).ceil() as usize;
+        let index = rank.clamp(1, latencies.len()) - 1;
+        Some(*latencies.select_nth_unstable(index).1)
+    }
+
     pub fn register_result<T, E>(&mut self, res: &Result<T, E>) {
+        self.register_weighted_result(res, 1.0)
+    }
+
+    /// Records a result, counting a failure with the given weight
+    /// towards the error threshold.
+    pub fn register_weighted_result<T, E>(&mut self, res: &Result<T, E>, weight: f64) {
         match *res {
             Ok(_) => self.circuit_breaker_stats.add_point(Point::SUCCESS),
-            Err(_) => self.circuit_breaker_stats.add_point(Point::FAILURE),
+            Err(_) => self
+                .circuit_breaker_stats
+                .add_weighted_point(Point::FAILURE, weight),
+        }
+
+        match *res {
+            Ok(_) => self.consecutive_failures = 0,
+            Err(_) => self.consecutive_failures = self.consecutive_failures.saturating_add(1),
+        }
+
+        if let Some(ref shared_window) = self.shared_window {
+            let mut shared_window = shared_window.lock().unwrap();
+            match *res {
+                Ok(_) => shared_window.add_point(Point::SUCCESS),
+                Err(_) => shared_window.add_weighted_point(Point::FAILURE, weight),
+            }
+        }
+
+        if let Some(ref mut reporting_stats) = self.reporting_stats {
+            match *res {
+                Ok(_) => reporting_stats.add_point(Point::SUCCESS),
+                Err(_) => reporting_stats.add_weighted_point(Point::FAILURE, weight),
+            }
+        }
+
+        if self.probing {
+            self.register_probe_result(res.is_ok());
+        }
+
+        // A failure during recovery starts the ramp over:
+        if res.is_err() && self.recovery_start.is_some() {
+            self.recovery_start = Some(self.clock.now());
+        }
+
+        if res.is_err() && self.is_failure_burst() && !self.in_warmup() {
+            self.open_circuit();
+        }
+    }
+
+    /// Feed recorded outcomes through the breaker as if calls with
+    /// these outcomes had been made at the given times, and return
+    /// the resulting state transitions. Outcomes of calls the breaker
+    /// would have rejected are ignored. Events must be in
+    /// chronological order.
+    ///
+    /// Replaying changes the breaker's state like real calls would,
+    /// so it is usually done on a freshly created breaker.
+    pub fn replay(&mut self, events: &[(Point, Instant)]) -> Vec<(Instant, CircuitState)> {
+        let clock = self.clock.clone();
+        let warmup_start = self.warmup_start;
+        let replay_clock = Arc::new(ManualClock::new());
+        self.set_clock(replay_clock.clone());
+
+        let mut timeline = Vec::new();
+        for &(point, time) in events {
+            replay_clock.set(time);
+            let previous_state = self.state();
+            if self.check_command_allowed() {
+                let res = match point {
+                    Point::SUCCESS => Ok(()),
+                    Point::FAILURE => Err(()),
+                };
+                self.register_result(&res);
+            }
+
+            let state = self.state();
+            if state != previous_state {
+                timeline.push((time, state));
+            }
+        }
+
+        self.set_clock(clock);
+        self.warmup_start = warmup_start;
+        timeline
+    }
+
+    /// Warnings about the configuration the breaker was created with,
+    /// see `Config::validate`. The configuration is valid, but likely
+    /// not what was intended.
+    pub fn config_warnings(&self) -> &[ConfigWarning] {
+        &self.config_warnings
+    }
+
+    /// How long the circuit stays open. While the circuit is open,
+    /// this is the duration applied to the current opening, i.e.
+    /// raised to `open_duration_floor_ms` plus some jitter if it was
+    /// shorter. While the circuit is closed, this is the duration it
+    /// opens for next before the floor is applied: `circuit_open_ms`,
+    /// unless the open duration is adaptive.
+    pub fn open_duration(&self) -> Duration {
+        if self.circuit_open_time.is_some() {
+            self.effective_open
+        } else {
+            Duration::from_millis(self.open_ms)
+        }
+    }
+
+    /// The current time according to the breaker's clock.
+    pub(crate) fn now(&self) -> Instant {
+        self.clock.now()
+    }
+
+    /// Records the result returned to the caller. A fallback value
+    /// counts as a success here, even though the breaker recorded the
+    /// underlying call as a failure.
+    pub fn register_user_visible_result<T, E>(&mut self, res: &Result<T, E>) {
+        match *res {
+            Ok(_) => self.user_visible_stats.add_point(Point::SUCCESS),
+            Err(_) => self.user_visible_stats.add_point(Point::FAILURE),
+        }
+    }
+
+    /// Records a result under the given tag in addition to
+    /// `register_result`. Tagged results are only reported through
+    /// `metrics_by_tag`.
+    pub fn register_tagged_result<T, E>(&mut self, res: &Result<T, E>, tag: &'static str) {
+        if !self.tagged_stats.contains_key(tag) {
+            // Start from an empty copy of the breaker's window:
+            let mut window = self.circuit_breaker_stats.window.clone();
+            window.clear_window();
+            self.tagged_stats.insert(tag, CircuitBreakerStats { window });
+        }
+
+        let stats = self.tagged_stats.get_mut(tag).unwrap();
+        match *res {
+            Ok(_) => stats.add_point(Point::SUCCESS),
+            Err(_) => stats.add_point(Point::FAILURE),
+        }
+    }
+
+    /// Statistics of the outcomes of the command calls, which drive
+    /// the breaker's decisions.
+    pub fn stats(&mut self) -> &mut CircuitBreakerStats {
+        &mut self.circuit_breaker_stats
+    }
+
+    /// Statistics the metrics are drawn from. These are the statistics
+    /// of the reporting window if `reporting_buckets_in_window` is set,
+    /// or the ones driving the breaker's decisions otherwise.
+    pub fn reporting_stats(&mut self) -> &mut CircuitBreakerStats {
+        match self.reporting_stats {
+            Some(ref mut reporting_stats) => reporting_stats,
+            None => &mut self.circuit_breaker_stats,
+        }
+    }
+
+    /// Share of the calls in the window that were rejected, between
+    /// 0.0 and 1.0. Calls made while the breaker is disabled or that
+    /// bypass it are not counted.
+    pub fn rejection_rate(&mut self) -> f64 {
+        self.admission_stats.error_rate()
+    }
+
+    /// Statistics of the results returned to callers, see
+    /// `register_user_visible_result`.
+    pub fn user_visible_stats(&mut self) -> &mut CircuitBreakerStats {
+        &mut self.user_visible_stats
+    }
+
+    /// Adapts the open duration to the outcome of a probe. A failed
+    /// probe doubles the duration (up to `max_open_ms`) and reopens
+    /// the circuit, a successful one resets it to `min_open_ms`.
+    fn register_probe_result(&mut self, success: bool) {
+        self.probing = false;
+        if success {
+            self.open_ms = self.config.min_open_ms;
+        } else {
+            self.open_ms = self
+                .open_ms
+                .saturating_mul(2)
+                .min(self.config.max_open_ms)
+                .max(self.config.min_open_ms);
+            self.open_circuit();
+        }
+    }
+
+    /// Records a failure and returns whether it completes a burst of
+    /// `burst_threshold` failures, which opens the circuit regardless
+    /// of the window's error rate.
+    fn is_failure_burst(&mut self) -> bool {
+        let (failures, within) = match self.config.burst_threshold {
+            Some(burst_threshold) => burst_threshold,
+            None => return false,
+        };
+
+        let now = self.clock.now();
+        self.recent_failures.push_back(now);
+        while self.recent_failures.len() > failures as usize {
+            self.recent_failures.pop_front();
+        }
+
+        let is_burst = self.recent_failures.len() == failures as usize
+            && self
+                .recent_failures
+                .front()
+                .map(|&first| now.saturating_duration_since(first) <= within)
+                .unwrap_or(false);
+        if is_burst {
+            self.recent_failures.clear();
+        }
+        is_burst
+    }
+
+    /// Returns whether the probe budget allows closing the circuit for
+    /// another probe, and if so, counts the probe against the budget.
+    fn take_probe(&mut self) -> bool {
+        match self.config.probe_budget {
+            Some(probe_budget) => {
+                take_budget(&mut self.recent_probes, probe_budget, self.clock.now())
+            }
+            None => true,
+        }
+    }
+
+    /// Returns whether the fallback rate limit allows using the
+    /// fallback for a rejected call, and if so, counts the fallback
+    /// against the limit. See `Config::max_fallback_rate`.
+    pub(crate) fn take_fallback(&mut self) -> bool {
+        match self.config.max_fallback_rate {
+            Some(max_fallback_rate) => take_budget(
+                &mut self.recent_fallbacks,
+                max_fallback_rate,
+                self.clock.now(),
+            ),
+            None => true,
+        }
+    }
+
+    /// Whether the guarded latency percentile exceeds its threshold,
+    /// see `Config::latency_trend_guard`.
+    fn latency_exceeds_guard(&mut self) -> bool {
+        let guard = match self.config.latency_trend_guard {
+            Some(guard) => guard,
+            None => return false,
+        };
+
+        match self.latency_percentile(guard.p) {
+            Some(latency) => latency > Duration::from_millis(guard.threshold_ms),
+            None => false,
+        }
+    }
+
+    /// Drops latencies recorded before the start of the window.
+    fn prune_latencies(&mut self) {
+        let window = Duration::from_millis(
+            self.config.bucket_size_in_ms * u64::from(self.config.buckets_in_window),
+        );
+        let now = self.clock.now();
+        while let Some(&(recorded_at, _)) = self.recent_latencies.front() {
+            if now.saturating_duration_since(recorded_at) < window {
+                break;
+            }
+            self.recent_latencies.pop_front();
         }
     }
 
     fn should_close_open_circuit(&mut self) -> bool {
         if let Some(open_time) = self.circuit_open_time {
-            open_time <= self.time_to_close_circuit()
+            self.open_duration_elapsed(open_time)
         } else {
             false
         }
@@ -58,23 +774,195 @@ impl CircuitBreaker {
 
     fn should_keep_circuit_open(&mut self) -> bool {
         if let Some(open_time) = self.circuit_open_time {
-            open_time > self.time_to_close_circuit()
+            !self.open_duration_elapsed(open_time)
         } else {
             false
         }
     }
 
+    fn open_circuit(&mut self) {
+        self.recovery_start = None;
+        self.effective_open = self.effective_open_duration();
+        self.circuit_open_time = Some(self.clock.now());
+        self.circuit_breaker_stats.clear();
+        self.consecutive_failures = 0;
+    }
+
+    /// Whether the breaker was created less than `warmup_ms` ago.
+    /// The circuit doesn't open during warmup.
+    fn in_warmup(&self) -> bool {
+        match self.config.warmup_ms {
+            Some(warmup_ms) => {
+                let elapsed = self
+                    .clock
+                    .now()
+                    .saturating_duration_since(self.warmup_start);
+                elapsed < Duration::from_millis(warmup_ms)
+            }
+            None => false,
+        }
+    }
+
     fn should_open_circuit(&mut self) -> bool {
-        let pct_above_threshold =
-            self.circuit_breaker_stats.error_percentage() >= self.config.error_threshold_percentage;
+        if self.in_warmup() {
+            return false;
+        }
 
-        let count_above_threshold =
-            self.circuit_breaker_stats.error_nr() >= self.config.error_threshold;
+        if let BreakerMode::ConsecutiveFailures(failures) = self.config.mode {
+            return self.consecutive_failures >= failures;
+        }
+
+        let (error_percentage, error_score) = self.error_stats();
+
+        // Without failures, the circuit stays closed even if both
+        // thresholds are 0. A 0% threshold thus means that any failure
+        // counts once the count threshold is met:
+        if error_score <= 0.0 {
+            return false;
+        }
+
+        let pct_above_threshold = error_percentage >= self.config.error_threshold_percentage;
+        let count_above_threshold = error_score >= f64::from(self.config.error_threshold);
 
         pct_above_threshold && count_above_threshold
     }
 
-    fn time_to_close_circuit(&self) -> Instant {
-        Instant::now() - Duration::from_millis(self.config.circuit_open_ms)
+    /// Returns the share of calls to admit in the current stage of the
+    /// recovery ramp, or `None` once the ramp is over.
+    fn recovery_fraction(&mut self) -> Option<f64> {
+        let recovery_ramp = self.config.recovery_ramp?;
+        let recovery_start = self.recovery_start?;
+
+        let elapsed = self.clock.now().saturating_duration_since(recovery_start);
+        let mut stage_end = Duration::from_millis(0);
+        for &(fraction, duration) in recovery_ramp {
+            stage_end += duration;
+            if elapsed < stage_end {
+                return Some(fraction);
+            }
+        }
+
+        self.recovery_start = None;
+        None
+    }
+
+    /// Whether a call of the given class should be rejected because
+    /// the error percentage and score reach the class's thresholds.
+    fn should_shed_class(&mut self, class: Option<OperationClass>) -> bool {
+        let threshold_percentage = match class {
+            Some(OperationClass::Read) => self.config.read_error_threshold_percentage,
+            Some(OperationClass::Write) => self.config.write_error_threshold_percentage,
+            None => None,
+        };
+        let threshold_percentage = match threshold_percentage {
+            Some(threshold_percentage) => threshold_percentage,
+            None => return false,
+        };
+
+        let (error_percentage, error_score) = self.error_stats();
+        error_score > 0.0
+            && error_percentage >= threshold_percentage
+            && error_score >= f64::from(self.config.error_threshold)
+    }
+
+    /// Returns the error percentage and the failure score deciding
+    /// whether to open the circuit, drawn from the shared window if
+    /// there is one.
+    fn error_stats(&mut self) -> (i32, f64) {
+        let recent_buckets = self.config.count_window_buckets;
+        match self.shared_window {
+            Some(ref shared_window) => {
+                error_stats(&mut shared_window.lock().unwrap(), recent_buckets)
+            }
+            None => error_stats(&mut self.circuit_breaker_stats.window, recent_buckets),
+        }
+    }
+
+    /// Whether the circuit has been open for `circuit_open_ms`. Should
+    /// the clock have gone backwards past the open time, the circuit
+    /// counts as just opened.
+    fn open_duration_elapsed(&self, open_time: Instant) -> bool {
+        // A latched circuit stays open until it is reset:
+        if self.config.latch_open {
+            return false;
+        }
+
+        let open_for = self.clock.now().saturating_duration_since(open_time);
+        open_for >= self.effective_open
+    }
+
+    /// Open durations below `open_duration_floor_ms` are spread
+    /// randomly between the floor and twice the floor, so breakers
+    /// with tiny open durations don't all probe at the same time.
+    fn effective_open_duration(&self) -> Duration {
+        let floor = self.config.open_duration_floor_ms;
+        if self.open_ms >= floor {
+            return Duration::from_millis(self.open_ms);
+        }
+
+        Duration::from_millis(floor).mul_f64(1.0 + self.rng.next_f64())
+    }
+}
+
+/// Returns the error percentage of the window and its failure score,
+/// counting only the given number of most recent buckets towards the
+/// score if set.
+/// The metrics computed by `metrics_cached` and when they were
+/// computed. Behind a mutex rather than a `Cell`, so breakers can be
+/// scraped from other threads through a shared reference.
+#[derive(Debug, Default)]
+struct MetricsCache(Mutex<Option<(Instant, Metrics)>>);
+
+impl Clone for MetricsCache {
+    fn clone(&self) -> Self {
+        MetricsCache(Mutex::new(*self.0.lock().unwrap()))
+    }
+}
+
+fn error_stats(window: &mut Window, recent_buckets: Option<u32>) -> (i32, f64) {
+    let points = window.get_points();
+    let error_percentage = if points.is_empty() {
+        0
+    } else {
+        let error_nr = points
+            .iter()
+            .filter(|&&point| point == Point::FAILURE)
+            .count();
+        (error_nr * 100 / points.len()) as i32
+    };
+
+    let error_score = match recent_buckets {
+        Some(buckets) => window.get_recent_failure_score(buckets),
+        None => window.get_failure_score(),
+    };
+    (error_percentage, error_score)
+}
+
+/// Returns whether a budget of `(events, per)` allows another event
+/// at `now`, given the times of the recent events, and if so, records
+/// the event.
+fn take_budget(recent: &mut VecDeque<Instant>, budget: (u32, Duration), now: Instant) -> bool {
+    let (events, per) = budget;
+    while let Some(&event) = recent.front() {
+        if now.saturating_duration_since(event) < per {
+            break;
+        }
+        recent.pop_front();
+    }
+
+    if recent.len() >= events as usize {
+        return false;
+    }
+    recent.push_back(now);
+    true
+}
+
+/// Returns the open duration in ms of a new breaker. With
+/// `adaptive_open_duration`, it starts out at `min_open_ms`.
+fn initial_open_ms(config: &Config) -> u64 {
+    if config.adaptive_open_duration {
+        config.min_open_ms
+    } else {
+        config.circuit_open_ms
     }
 }
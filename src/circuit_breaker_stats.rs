@@ -11,36 +11,64 @@ impl CircuitBreakerStats {
         self.window.add_point(point)
     }
 
+    pub fn add_weighted_point(&mut self, point: Point, weight: f64) {
+        self.window.add_weighted_point(point, weight)
+    }
+
     pub fn clear(&mut self) {
         self.window.clear_window()
     }
 
     pub fn success_percentage(&mut self) -> i32 {
-        let points = self.window.get_points();
         let success_nr = self.success_nr();
-        if success_nr == 0 {
-            return 0;
-        } else {
-            return (success_nr / points.len() as i32) * 100;
-        }
+        self.percentage_of_points(success_nr)
     }
 
     pub fn error_percentage(&mut self) -> i32 {
-        let points = self.window.get_points();
         let error_nr = self.error_nr();
+        self.percentage_of_points(error_nr)
+    }
 
-        if error_nr == 0 {
-            return 0;
-        } else {
-            return (error_nr / points.len() as i32) * 100;
+    /// Share of failures among all points in the window, between 0.0
+    /// and 1.0. An empty window has an error rate of 0.0.
+    pub fn error_rate(&mut self) -> f64 {
+        if self.window.is_empty() {
+            return 0.0;
         }
+
+        f64::from(self.error_nr()) / self.window.get_points().len() as f64
+    }
+
+    /// Share of successes among all points in the window, between 0.0
+    /// and 1.0. An empty window has a success rate of 0.0.
+    pub fn success_rate(&mut self) -> f64 {
+        if self.window.is_empty() {
+            return 0.0;
+        }
+
+        f64::from(self.success_nr()) / self.window.get_points().len() as f64
+    }
+
+    /// Like `error_rate`, but only considers the points of the `n`
+    /// most recent valid buckets.
+    pub fn error_rate_last_buckets(&mut self, n: usize) -> f64 {
+        let points = self.window.get_recent_points(n);
+        if points.is_empty() {
+            return 0.0;
+        }
+
+        let error_nr = points
+            .iter()
+            .filter(|&&point| point == Point::FAILURE)
+            .count();
+        error_nr as f64 / points.len() as f64
     }
 
     pub fn success_nr(&mut self) -> i32 {
         let points = self.window.get_points();
         let success_count = points
             .iter()
-            .filter(|&&point| return point == Point::SUCCESS)
+            .filter(|&&point| point == Point::SUCCESS)
             .collect::<Vec<_>>()
             .len();
 
@@ -51,10 +79,30 @@ impl CircuitBreakerStats {
         let points = self.window.get_points();
         let error_count = points
             .iter()
-            .filter(|&&point| return point == Point::FAILURE)
+            .filter(|&&point| point == Point::FAILURE)
             .collect::<Vec<_>>()
             .len();
 
         error_count as i32
     }
+
+    /// Sum of the weights of all failures in the window. Equals
+    /// `error_nr` if no failure weights are in use.
+    pub fn error_score(&mut self) -> f64 {
+        self.window.get_failure_score()
+    }
+
+    /// Like `error_score`, but only sums the failures of the given
+    /// number of most recent buckets.
+    pub fn recent_error_score(&mut self, buckets: u32) -> f64 {
+        self.window.get_recent_failure_score(buckets)
+    }
+
+    fn percentage_of_points(&mut self, nr: i32) -> i32 {
+        if self.window.is_empty() {
+            return 0;
+        }
+
+        nr * 100 / self.window.get_points().len() as i32
+    }
 }
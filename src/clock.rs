@@ -0,0 +1,66 @@
+use std::fmt::Debug;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// A source of the current time. Circuit breakers and windows read
+/// the time through a clock so it can be controlled in tests.
+pub trait Clock: Debug + Send + Sync {
+    fn now(&self) -> Instant;
+}
+
+/// Clock reading the actual system time. This is the default clock.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// Clock that only changes when told to. Useful to test time
+/// dependent breaker behavior without sleeping. It may also be moved
+/// backwards to simulate misbehaving time sources.
+#[derive(Debug)]
+pub struct ManualClock {
+    now: Mutex<Instant>,
+}
+
+impl ManualClock {
+    /// Create a clock starting at the current system time.
+    pub fn new() -> ManualClock {
+        ManualClock {
+            now: Mutex::new(Instant::now()),
+        }
+    }
+
+    pub fn set(&self, now: Instant) {
+        *self.now.lock().unwrap() = now;
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        let mut now = self.now.lock().unwrap();
+        *now += duration;
+    }
+
+    /// Move the clock backwards. Does nothing if the resulting instant
+    /// can not be represented.
+    pub fn rewind(&self, duration: Duration) {
+        let mut now = self.now.lock().unwrap();
+        if let Some(earlier) = now.checked_sub(duration) {
+            *now = earlier;
+        }
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> ManualClock {
+        ManualClock::new()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        *self.now.lock().unwrap()
+    }
+}
@@ -1,6 +1,18 @@
-use error::CriusError;
-use circuit_breaker::CircuitBreaker;
+use error::{ClassifierError, CriusError};
+use latency_histogram::LatencyHistogram;
+use circuit_breaker::{CircuitBreaker, CircuitState, Metrics};
+use clock::Clock;
+use coordination::CoordinationSink;
+use observer::{Observer, Outcome};
+use rng::Rng;
+use std::any::Any;
+use std::collections::HashMap;
 use std::marker::PhantomData;
+use std::ops::ControlFlow;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use window::{ExportedWindow, Point, Window, WindowCoverage};
 
 const DEFAULT_ERROR_THRESHOLD: i32 = 10;
 const DEFAULT_ERROR_THRESHOLD_PERCENTAGE: i32 = 50;
@@ -8,6 +20,27 @@ const DEFAULT_BUCKETS_IN_WINDOW: u32 = 10;
 const DEFAULT_BUCKET_SIZE_IN_MS: u64 = 1000;
 const DEFAULT_CIRCUIT_OPEN_MS: u64 = 5000;
 const DEFAULT_CIRCUIT_BREAKER_ENABLED: bool = true;
+const DEFAULT_MAX_RETRIES: u32 = 0;
+const DEFAULT_PREFER_ERROR_ON_OPEN: bool = false;
+const DEFAULT_SHORT_WINDOW_IS_ERROR: bool = false;
+const DEFAULT_ADAPTIVE_OPEN_DURATION: bool = false;
+const DEFAULT_MIN_OPEN_MS: u64 = 1000;
+const DEFAULT_MAX_OPEN_MS: u64 = 60000;
+const DEFAULT_BURST_THRESHOLD: Option<(u32, Duration)> = None;
+const DEFAULT_REPORTING_BUCKETS_IN_WINDOW: Option<u32> = None;
+const DEFAULT_PROBE_BUDGET: Option<(u32, Duration)> = None;
+const DEFAULT_COUNT_WINDOW_BUCKETS: Option<u32> = None;
+const DEFAULT_MODE: BreakerMode = BreakerMode::Rolling;
+const DEFAULT_GRADUATED_SHEDDING: bool = false;
+const DEFAULT_LATCH_OPEN: bool = false;
+const DEFAULT_OPEN_DURATION_FLOOR_MS: u64 = 10;
+const DEFAULT_WARMUP_MS: Option<u64> = None;
+const DEFAULT_LATENCY_TREND_GUARD: Option<LatencyGuard> = None;
+const DEFAULT_MAX_TOTAL_CALLS: Option<u64> = None;
+const DEFAULT_READ_ERROR_THRESHOLD_PERCENTAGE: Option<i32> = None;
+const DEFAULT_WRITE_ERROR_THRESHOLD_PERCENTAGE: Option<i32> = None;
+const DEFAULT_RECOVERY_RAMP: Option<&'static [(f64, Duration)]> = None;
+const DEFAULT_MAX_FALLBACK_RATE: Option<(u32, Duration)> = None;
 
 #[derive(Copy, Clone, Debug)]
 pub struct Config {
@@ -17,10 +50,31 @@ pub struct Config {
     pub bucket_size_in_ms: u64,
     pub circuit_open_ms: u64,
     pub circuit_breaker_enabled: bool,
+    pub max_retries: u32,
+    pub prefer_error_on_open: bool,
+    pub short_window_is_error: bool,
+    pub adaptive_open_duration: bool,
+    pub min_open_ms: u64,
+    pub max_open_ms: u64,
+    pub burst_threshold: Option<(u32, Duration)>,
+    pub reporting_buckets_in_window: Option<u32>,
+    pub probe_budget: Option<(u32, Duration)>,
+    pub count_window_buckets: Option<u32>,
+    pub mode: BreakerMode,
+    pub graduated_shedding: bool,
+    pub latch_open: bool,
+    pub open_duration_floor_ms: u64,
+    pub warmup_ms: Option<u64>,
+    pub latency_trend_guard: Option<LatencyGuard>,
+    pub max_total_calls: Option<u64>,
+    pub read_error_threshold_percentage: Option<i32>,
+    pub write_error_threshold_percentage: Option<i32>,
+    pub recovery_ramp: Option<&'static [(f64, Duration)]>,
+    pub max_fallback_rate: Option<(u32, Duration)>,
 }
 
-impl Config {
-    pub fn default() -> Config {
+impl Default for Config {
+    fn default() -> Config {
         Config {
             error_threshold: DEFAULT_ERROR_THRESHOLD,
             error_threshold_percentage: DEFAULT_ERROR_THRESHOLD_PERCENTAGE,
@@ -28,40 +82,472 @@ impl Config {
             bucket_size_in_ms: DEFAULT_BUCKET_SIZE_IN_MS,
             circuit_open_ms: DEFAULT_CIRCUIT_OPEN_MS,
             circuit_breaker_enabled: DEFAULT_CIRCUIT_BREAKER_ENABLED,
+            max_retries: DEFAULT_MAX_RETRIES,
+            prefer_error_on_open: DEFAULT_PREFER_ERROR_ON_OPEN,
+            short_window_is_error: DEFAULT_SHORT_WINDOW_IS_ERROR,
+            adaptive_open_duration: DEFAULT_ADAPTIVE_OPEN_DURATION,
+            min_open_ms: DEFAULT_MIN_OPEN_MS,
+            max_open_ms: DEFAULT_MAX_OPEN_MS,
+            burst_threshold: DEFAULT_BURST_THRESHOLD,
+            reporting_buckets_in_window: DEFAULT_REPORTING_BUCKETS_IN_WINDOW,
+            probe_budget: DEFAULT_PROBE_BUDGET,
+            count_window_buckets: DEFAULT_COUNT_WINDOW_BUCKETS,
+            mode: DEFAULT_MODE,
+            graduated_shedding: DEFAULT_GRADUATED_SHEDDING,
+            latch_open: DEFAULT_LATCH_OPEN,
+            open_duration_floor_ms: DEFAULT_OPEN_DURATION_FLOOR_MS,
+            warmup_ms: DEFAULT_WARMUP_MS,
+            latency_trend_guard: DEFAULT_LATENCY_TREND_GUARD,
+            max_total_calls: DEFAULT_MAX_TOTAL_CALLS,
+            read_error_threshold_percentage: DEFAULT_READ_ERROR_THRESHOLD_PERCENTAGE,
+            write_error_threshold_percentage: DEFAULT_WRITE_ERROR_THRESHOLD_PERCENTAGE,
+            recovery_ramp: DEFAULT_RECOVERY_RAMP,
+            max_fallback_rate: DEFAULT_MAX_FALLBACK_RATE,
         }
     }
+}
 
+impl Config {
     pub fn error_threshold(&mut self, error_threshold: i32) -> &mut Self {
         self.error_threshold = error_threshold;
-        return self;
+        self
     }
 
     pub fn error_threshold_percentage(&mut self, error_threshold_percentage: i32) -> &mut Self {
         self.error_threshold_percentage = error_threshold_percentage;
-        return self;
+        self
     }
 
     pub fn buckets_in_window(&mut self, buckets_in_window: u32) -> &mut Self {
         self.buckets_in_window = buckets_in_window;
-        return self;
+        self
     }
 
     pub fn bucket_size_in_ms(&mut self, bucket_size_in_ms: u64) -> &mut Self {
         self.bucket_size_in_ms = bucket_size_in_ms;
-        return self;
+        self
     }
 
     pub fn circuit_open_ms(&mut self, circuit_open_ms: u64) -> &mut Self {
         self.circuit_open_ms = circuit_open_ms;
-        return self;
+        self
     }
 
     pub fn circuit_breaker_enabled(&mut self, circuit_breaker_enabled: bool) -> &mut Self {
         self.circuit_breaker_enabled = circuit_breaker_enabled;
-        return self;
+        self
+    }
+
+    pub fn max_retries(&mut self, max_retries: u32) -> &mut Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    pub fn prefer_error_on_open(&mut self, prefer_error_on_open: bool) -> &mut Self {
+        self.prefer_error_on_open = prefer_error_on_open;
+        self
+    }
+
+    pub fn short_window_is_error(&mut self, short_window_is_error: bool) -> &mut Self {
+        self.short_window_is_error = short_window_is_error;
+        self
+    }
+
+    pub fn adaptive_open_duration(&mut self, adaptive_open_duration: bool) -> &mut Self {
+        self.adaptive_open_duration = adaptive_open_duration;
+        self
+    }
+
+    pub fn min_open_ms(&mut self, min_open_ms: u64) -> &mut Self {
+        self.min_open_ms = min_open_ms;
+        self
+    }
+
+    pub fn max_open_ms(&mut self, max_open_ms: u64) -> &mut Self {
+        self.max_open_ms = max_open_ms;
+        self
+    }
+
+    pub fn burst_threshold(&mut self, burst_threshold: Option<(u32, Duration)>) -> &mut Self {
+        self.burst_threshold = burst_threshold;
+        self
+    }
+
+    pub fn reporting_buckets_in_window(
+        &mut self,
+        reporting_buckets_in_window: Option<u32>,
+    ) -> &mut Self {
+        self.reporting_buckets_in_window = reporting_buckets_in_window;
+        self
+    }
+
+    pub fn probe_budget(&mut self, probe_budget: Option<(u32, Duration)>) -> &mut Self {
+        self.probe_budget = probe_budget;
+        self
+    }
+
+    pub fn count_window_buckets(&mut self, count_window_buckets: Option<u32>) -> &mut Self {
+        self.count_window_buckets = count_window_buckets;
+        self
+    }
+
+    pub fn mode(&mut self, mode: BreakerMode) -> &mut Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn graduated_shedding(&mut self, graduated_shedding: bool) -> &mut Self {
+        self.graduated_shedding = graduated_shedding;
+        self
+    }
+
+    pub fn latch_open(&mut self, latch_open: bool) -> &mut Self {
+        self.latch_open = latch_open;
+        self
+    }
+
+    pub fn open_duration_floor_ms(&mut self, open_duration_floor_ms: u64) -> &mut Self {
+        self.open_duration_floor_ms = open_duration_floor_ms;
+        self
+    }
+
+    pub fn warmup_ms(&mut self, warmup_ms: Option<u64>) -> &mut Self {
+        self.warmup_ms = warmup_ms;
+        self
+    }
+
+    pub fn latency_trend_guard(&mut self, latency_trend_guard: Option<LatencyGuard>) -> &mut Self {
+        self.latency_trend_guard = latency_trend_guard;
+        self
+    }
+
+    pub fn max_total_calls(&mut self, max_total_calls: Option<u64>) -> &mut Self {
+        self.max_total_calls = max_total_calls;
+        self
+    }
+
+    pub fn read_error_threshold_percentage(
+        &mut self,
+        read_error_threshold_percentage: Option<i32>,
+    ) -> &mut Self {
+        self.read_error_threshold_percentage = read_error_threshold_percentage;
+        self
+    }
+
+    pub fn write_error_threshold_percentage(
+        &mut self,
+        write_error_threshold_percentage: Option<i32>,
+    ) -> &mut Self {
+        self.write_error_threshold_percentage = write_error_threshold_percentage;
+        self
+    }
+
+    pub fn recovery_ramp(
+        &mut self,
+        recovery_ramp: Option<&'static [(f64, Duration)]>,
+    ) -> &mut Self {
+        self.recovery_ramp = recovery_ramp;
+        self
+    }
+
+    pub fn max_fallback_rate(&mut self, max_fallback_rate: Option<(u32, Duration)>) -> &mut Self {
+        self.max_fallback_rate = max_fallback_rate;
+        self
+    }
+
+    /// Check the configuration for problems. Invalid configurations
+    /// return `CriusError::InvalidConfig`, while configurations that
+    /// are valid but likely unintended return warnings. Breakers
+    /// validate their configuration when they are created.
+    pub fn validate(&self) -> Result<Vec<ConfigWarning>, CriusError> {
+        self.validate_all().map_err(|_| CriusError::InvalidConfig)
+    }
+
+    /// Like `validate`, but reports every reason the configuration is
+    /// invalid instead of only that it is.
+    pub fn validate_all(&self) -> Result<Vec<ConfigWarning>, Vec<ConfigProblem>> {
+        let mut problems = Vec::new();
+        let window_ms = self
+            .bucket_size_in_ms
+            .checked_mul(u64::from(self.buckets_in_window));
+        if window_ms.is_none() {
+            problems.push(ConfigProblem::WindowOverflow);
+        }
+
+        if self.error_threshold < 0 {
+            problems.push(ConfigProblem::NegativeErrorThreshold);
+        }
+
+        if !(0..=100).contains(&self.error_threshold_percentage) {
+            problems.push(ConfigProblem::ErrorThresholdPercentageOutOfRange);
+        }
+
+        let class_percentages = [
+            (OperationClass::Read, self.read_error_threshold_percentage),
+            (OperationClass::Write, self.write_error_threshold_percentage),
+        ];
+        for &(class, percentage) in &class_percentages {
+            match percentage {
+                Some(percentage) if !(0..=100).contains(&percentage) => problems.push(
+                    ConfigProblem::ClassErrorThresholdPercentageOutOfRange(class),
+                ),
+                _ => (),
+            }
+        }
+
+        if let Some(recovery_ramp) = self.recovery_ramp {
+            if recovery_ramp
+                .iter()
+                .any(|&(fraction, _)| !(0.0..=1.0).contains(&fraction))
+            {
+                problems.push(ConfigProblem::RecoveryRampFractionOutOfRange);
+            }
+        }
+
+        if self.adaptive_open_duration && self.min_open_ms > self.max_open_ms {
+            problems.push(ConfigProblem::MinOpenAboveMaxOpen);
+        }
+
+        if let Some((0, _)) = self.burst_threshold {
+            problems.push(ConfigProblem::ZeroBurstThreshold);
+        }
+
+        if let Some((0, _)) = self.probe_budget {
+            problems.push(ConfigProblem::ZeroProbeBudget);
+        }
+
+        if let Some((0, _)) = self.max_fallback_rate {
+            problems.push(ConfigProblem::ZeroMaxFallbackRate);
+        }
+
+        if let Some(0) = self.max_total_calls {
+            problems.push(ConfigProblem::ZeroMaxTotalCalls);
+        }
+
+        if let BreakerMode::ConsecutiveFailures(0) = self.mode {
+            problems.push(ConfigProblem::ZeroConsecutiveFailures);
+        }
+
+        match self.count_window_buckets {
+            Some(buckets) if buckets == 0 || buckets > self.buckets_in_window => {
+                problems.push(ConfigProblem::CountWindowBucketsOutOfRange)
+            }
+            _ => (),
+        }
+
+        if let Some(guard) = self.latency_trend_guard {
+            if !(guard.p > 0.0 && guard.p <= 1.0) {
+                problems.push(ConfigProblem::LatencyGuardPercentileOutOfRange);
+            }
+        }
+
+        let mut warnings = Vec::new();
+        match window_ms {
+            Some(window_ms) if window_ms < self.circuit_open_ms => {
+                if self.short_window_is_error {
+                    problems.push(ConfigProblem::WindowShorterThanOpenDuration);
+                } else {
+                    warnings.push(ConfigWarning::WindowShorterThanOpenDuration);
+                }
+            }
+            _ => (),
+        }
+
+        if problems.is_empty() {
+            Ok(warnings)
+        } else {
+            Err(problems)
+        }
     }
 }
 
+/// How a breaker decides to open its circuit.
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum BreakerMode {
+    /// Open based on the errors in the rolling window, see
+    /// `error_threshold` and `error_threshold_percentage`.
+    Rolling,
+
+    /// Open after the given number of consecutive failures. Any
+    /// success resets the streak. The window is only used for
+    /// metrics.
+    ConsecutiveFailures(u32),
+}
+
+/// Class of a call made with `Command::run_classified`. Each class
+/// can be shed at its own error percentage, see
+/// `Config::read_error_threshold_percentage` and
+/// `Config::write_error_threshold_percentage`.
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum OperationClass {
+    Read,
+    Write,
+}
+
+/// Rejects calls while the given percentile of the latencies in the
+/// window exceeds a threshold, see `Config::latency_trend_guard`.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct LatencyGuard {
+    /// Percentile of the latencies to compare, between 0.0 (exclusive)
+    /// and 1.0, e.g. 0.95 for the 95th percentile
+    pub p: f64,
+
+    /// Latency in ms the percentile may reach before calls are
+    /// rejected
+    pub threshold_ms: u64,
+}
+
+/// Describes why a configuration is invalid, see
+/// `Config::validate_all`.
+#[non_exhaustive]
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum ConfigProblem {
+    /// The window (`buckets_in_window * bucket_size_in_ms`) overflows
+    WindowOverflow,
+
+    /// `error_threshold` is negative
+    NegativeErrorThreshold,
+
+    /// `error_threshold_percentage` is not between 0 and 100
+    ErrorThresholdPercentageOutOfRange,
+
+    /// The error threshold percentage of the class is not between 0
+    /// and 100
+    ClassErrorThresholdPercentageOutOfRange(OperationClass),
+
+    /// A fraction of `recovery_ramp` is not between 0.0 and 1.0
+    RecoveryRampFractionOutOfRange,
+
+    /// `min_open_ms` exceeds `max_open_ms` while
+    /// `adaptive_open_duration` is set
+    MinOpenAboveMaxOpen,
+
+    /// `burst_threshold` allows no failures
+    ZeroBurstThreshold,
+
+    /// `probe_budget` allows no probes
+    ZeroProbeBudget,
+
+    /// `max_fallback_rate` allows no fallbacks
+    ZeroMaxFallbackRate,
+
+    /// `max_total_calls` allows no calls
+    ZeroMaxTotalCalls,
+
+    /// `mode` opens the circuit after zero consecutive failures
+    ZeroConsecutiveFailures,
+
+    /// `count_window_buckets` is zero or exceeds `buckets_in_window`
+    CountWindowBucketsOutOfRange,
+
+    /// The percentile of `latency_trend_guard` is not in (0.0, 1.0]
+    LatencyGuardPercentileOutOfRange,
+
+    /// The window is shorter than `circuit_open_ms` while
+    /// `short_window_is_error` is set
+    WindowShorterThanOpenDuration,
+}
+
+/// Describes a configuration that is valid, but likely unintended.
+#[non_exhaustive]
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum ConfigWarning {
+    /// The window (`buckets_in_window * bucket_size_in_ms`) is shorter
+    /// than `circuit_open_ms`. By the time the circuit may close, the
+    /// window is empty again, which makes the breaker flap. This is an
+    /// error if `short_window_is_error` is set.
+    WindowShorterThanOpenDuration,
+}
+
+/// Output of `Command::run_degradable`, telling fresh values of the
+/// command function apart from values provided by the fallback.
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum Degradable<O> {
+    /// Value returned by the command function
+    Fresh(O),
+
+    /// Value provided by the fallback, and why the fallback was used
+    Degraded(O, DegradationReason),
+}
+
+impl<O> Degradable<O> {
+    pub fn is_degraded(&self) -> bool {
+        match *self {
+            Degradable::Fresh(_) => false,
+            Degradable::Degraded(_, _) => true,
+        }
+    }
+
+    /// Returns the value, regardless of whether it is degraded.
+    pub fn into_inner(self) -> O {
+        match self {
+            Degradable::Fresh(value) => value,
+            Degradable::Degraded(value, _) => value,
+        }
+    }
+}
+
+/// Why a degraded value was provided by the fallback.
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum DegradationReason {
+    /// The command function failed
+    Failed,
+
+    /// The call was rejected by an open circuit
+    Rejected,
+}
+
+/// Admission decision of a breaker, see `Command::on_decision`.
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum Decision {
+    Allowed,
+    Rejected(RejectionReason),
+}
+
+/// Why a breaker rejected a call.
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum RejectionReason {
+    /// The circuit is open
+    CircuitOpen,
+
+    /// The circuit is closed, but the call was shed, e.g. due to
+    /// `graduated_shedding`, the latency guard, the threshold of its
+    /// operation class or an external coordinator
+    Shed,
+}
+
+/// Describes a single attempt made while running a command.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct AttemptRecord {
+    /// Number of the attempt, starting at 1
+    pub attempt: u32,
+
+    /// Whether the attempt succeeded or failed
+    pub outcome: Point,
+
+    /// Time the command function took for this attempt
+    pub latency: Duration,
+}
+
+/// How a failure classifier records an error of the command
+/// function, see `Command::classify_failure`.
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum Classification {
+    /// Record the call as a success, e.g. for a "not found" response
+    Success,
+
+    /// Record the call as a failure
+    Failure,
+}
+
+/// Decides whether an error of the command function counts as a
+/// failure, see `Command::classify_failure`.
+pub type ClassifyFn<E> = fn(&E) -> Result<Classification, ClassifierError>;
+
+/// Tracks repeated errors and returns the factor escalating the
+/// weight of a call's failure, see `Command::escalate_repeated_errors`.
+type Escalation<E> = Box<dyn FnMut(Option<&E>) -> f64 + Send>;
+
 pub struct Command<I, O, E, F, FB>
 where
     E: From<CriusError>,
@@ -70,8 +556,17 @@ where
 {
     pub cmd: F,
     pub fallback: Option<FB>,
+    pub failure_weight: Option<fn(&E) -> f64>,
+    pub validate: Option<fn(&I) -> bool>,
+    pub intercept: Option<fn(O) -> O>,
+    pub fallback_predicate: Option<fn(&E) -> bool>,
+    pub on_decision: Option<fn(Decision)>,
+    pub rate_limit: Option<fn() -> bool>,
+    pub classify_failure: Option<ClassifyFn<E>>,
+    escalation: Option<Escalation<E>>,
     phantom_data: PhantomData<I>,
     circuit_breaker: CircuitBreaker,
+    observers: Vec<Box<dyn Observer + Send>>,
 }
 
 impl<I, O, E, F, FB> Command<I, O, E, F, FB>
@@ -82,10 +577,19 @@ where
 {
     pub fn define(cfg: Config, cmd: F) -> Result<Command<I, O, E, F, FB>, CriusError> {
         Ok(Command {
-            cmd: cmd,
+            cmd,
             fallback: None,
+            failure_weight: None,
+            validate: None,
+            intercept: None,
+            fallback_predicate: None,
+            on_decision: None,
+            rate_limit: None,
+            classify_failure: None,
+            escalation: None,
             phantom_data: PhantomData,
             circuit_breaker: CircuitBreaker::new(cfg)?,
+            observers: Vec::new(),
         })
     }
 
@@ -94,49 +598,734 @@ where
         cmd: F,
         fallback: FB,
     ) -> Result<Command<I, O, E, F, FB>, CriusError> {
-        Ok(Command {
-            cmd: cmd,
-            fallback: Some(fallback),
-            phantom_data: PhantomData,
-            circuit_breaker: CircuitBreaker::new(cfg)?,
-        })
+        let mut command = Command::define(cfg, cmd)?;
+        command.fallback = Some(fallback);
+        Ok(command)
+    }
+
+    /// Classify failures by weight. Every failure adds its weight to
+    /// the score compared against `error_threshold`, so heavier
+    /// failures (e.g. timeouts) trip the breaker sooner. Failures
+    /// weigh 1.0 if no classifier is set.
+    pub fn failure_weight(&mut self, failure_weight: fn(&E) -> f64) -> &mut Self {
+        self.failure_weight = Some(failure_weight);
+        self
+    }
+
+    /// Validate inputs before running the command. Inputs for which
+    /// the validation returns `false` are rejected with
+    /// `CriusError::InvalidInput` without running the command or
+    /// affecting the breaker's statistics.
+    pub fn validate(&mut self, validate: fn(&I) -> bool) -> &mut Self {
+        self.validate = Some(validate);
+        self
+    }
+
+    /// Rewrite successful outputs of the command function before they
+    /// are returned. The interceptor runs after the outcome has been
+    /// recorded, so it does not affect the breaker's statistics.
+    /// Values provided by the fallback are not intercepted.
+    pub fn intercept(&mut self, intercept: fn(O) -> O) -> &mut Self {
+        self.intercept = Some(intercept);
+        self
+    }
+
+    /// Only use the fallback for failures of the command function for
+    /// which the predicate returns `true`. Other errors are returned
+    /// to the caller. Rejections by an open circuit are not affected.
+    pub fn fallback_predicate(&mut self, fallback_predicate: fn(&E) -> bool) -> &mut Self {
+        self.fallback_predicate = Some(fallback_predicate);
+        self
+    }
+
+    /// Pass every admission decision of the breaker to the given
+    /// function before the command runs, e.g. to log the intent of
+    /// calls that end up rejected.
+    pub fn on_decision(&mut self, on_decision: fn(Decision)) -> &mut Self {
+        self.on_decision = Some(on_decision);
+        self
+    }
+
+    /// Decide with the given classifier whether an error of the
+    /// command function is recorded as a failure (e.g. based on a
+    /// parsed status code). Should the classifier fail or panic,
+    /// observers are notified through `Observer::on_classifier_error`
+    /// and the call is recorded as a failure. The error is returned to
+    /// the caller either way.
+    pub fn classify_failure(&mut self, classify_failure: ClassifyFn<E>) -> &mut Self {
+        self.classify_failure = Some(classify_failure);
+        self
+    }
+
+    /// Consult the given rate limit (e.g. a token bucket) before the
+    /// breaker. Calls for which it returns `false` are rejected with
+    /// `CriusError::RateLimited`, regardless of the state of the
+    /// circuit and without affecting the breaker's statistics.
+    pub fn rate_limit(&mut self, rate_limit: fn() -> bool) -> &mut Self {
+        self.rate_limit = Some(rate_limit);
+        self
+    }
+
+    /// Read the current time from the given clock instead of the
+    /// system time, e.g. to control time in tests. The warmup period
+    /// restarts at the clock's current time.
+    pub fn clock(&mut self, clock: Arc<dyn Clock>) -> &mut Self {
+        self.circuit_breaker.set_clock(clock);
+        self
+    }
+
+    /// Draw random numbers from the given generator, e.g. to make
+    /// `graduated_shedding` deterministic in tests.
+    pub fn rng(&mut self, rng: Arc<dyn Rng>) -> &mut Self {
+        self.circuit_breaker.set_rng(rng);
+        self
+    }
+
+    /// Let an external coordinator override the breaker's decisions,
+    /// see `CoordinationSink`. Without a sink, breakers decide based
+    /// on their local statistics only.
+    pub fn coordinate(&mut self, coordination: Arc<dyn CoordinationSink>) -> &mut Self {
+        self.circuit_breaker.set_coordination(coordination);
+        self
+    }
+
+    /// Record outcomes into the given window shared with other
+    /// commands as well, and open the circuit based on their combined
+    /// outcomes, see `CircuitBreaker::set_shared_window`.
+    pub fn share_window(&mut self, window: Arc<Mutex<Window>>) -> &mut Self {
+        self.circuit_breaker.set_shared_window(window);
+        self
+    }
+
+    /// Register an observer that is notified about the outcome of
+    /// every call and about state changes of the breaker.
+    pub fn add_observer<T: Observer + Send + 'static>(&mut self, observer: T) -> &mut Self {
+        self.observers.push(Box::new(observer));
+        self
+    }
+
+    pub(crate) fn add_boxed_observer(&mut self, observer: Box<dyn Observer + Send>) {
+        self.observers.push(observer);
+    }
+
+    /// The breaker deciding about the command's calls, e.g. for custom
+    /// execution strategies that check and record calls themselves.
+    ///
+    /// The breaker's methods are lower level than the command's and
+    /// may change between minor versions. Observers of the command are
+    /// not notified about anything done through the breaker directly.
+    pub fn breaker(&self) -> &CircuitBreaker {
+        &self.circuit_breaker
+    }
+
+    /// Like `breaker`, but allows checking calls against the breaker
+    /// and recording their outcomes.
+    pub fn breaker_mut(&mut self) -> &mut CircuitBreaker {
+        &mut self.circuit_breaker
+    }
+
+    pub fn metrics(&mut self) -> Metrics {
+        self.circuit_breaker.metrics()
+    }
+
+    /// Close the circuit and clear the breaker's windows, see
+    /// `CircuitBreaker::reset`.
+    pub fn reset(&mut self) {
+        let previous_state = self.circuit_breaker.state();
+        self.circuit_breaker.reset();
+        self.notify_state_change(previous_state, None);
+    }
+
+    /// Like `metrics`, but reuses metrics computed at most
+    /// `max_staleness` ago, see `CircuitBreaker::metrics_cached`.
+    pub fn metrics_cached(&self, max_staleness: Duration) -> Metrics {
+        self.circuit_breaker.metrics_cached(max_staleness)
+    }
+
+    /// Warnings about the configuration the command was defined with,
+    /// e.g. a window shorter than the open duration, see
+    /// `Config::validate`.
+    pub fn config_warnings(&self) -> &[ConfigWarning] {
+        self.circuit_breaker.config_warnings()
+    }
+
+    /// How long the circuit stays open, see
+    /// `CircuitBreaker::open_duration`.
+    pub fn open_duration(&self) -> Duration {
+        self.circuit_breaker.open_duration()
+    }
+
+    /// Metrics of the calls made with `run_tagged`, grouped by tag.
+    /// Rejected calls are not recorded under their tag.
+    pub fn metrics_by_tag(&mut self) -> HashMap<&'static str, Metrics> {
+        self.circuit_breaker.metrics_by_tag()
+    }
+
+    /// Export the window the breaker's decisions are based on, e.g.
+    /// to compare it against a golden file, see `Window::export`.
+    pub fn export_window(&mut self) -> ExportedWindow {
+        self.circuit_breaker.stats().window.export()
+    }
+
+    /// Replace the window the breaker's decisions are based on with
+    /// the given export, e.g. to set up a precise window in tests, see
+    /// `Window::import`. The state of the circuit does not change
+    /// until the next call.
+    pub fn import_window(&mut self, export: &ExportedWindow) -> &mut Self {
+        self.circuit_breaker.stats().window.import(export);
+        self
+    }
+
+    /// Share of failed calls of the command function in the current
+    /// window. This is the rate the breaker's decisions are based on.
+    pub fn error_rate(&mut self) -> f64 {
+        self.circuit_breaker.stats().error_rate()
+    }
+
+    /// How many calls and how much time the current window covers,
+    /// e.g. to tell an error rate over a few calls apart from one over
+    /// thousands, see `Window::coverage`.
+    pub fn window_coverage(&mut self) -> WindowCoverage {
+        self.circuit_breaker.stats().window.coverage()
+    }
+
+    /// Share of calls in the current window that returned `Ok` to the
+    /// caller, including values provided by the fallback.
+    pub fn user_visible_success_rate(&mut self) -> f64 {
+        self.circuit_breaker.user_visible_stats().success_rate()
+    }
+
+    /// Histogram of the time calls to the command function took since
+    /// the command was defined. Histograms of several commands can be
+    /// merged, e.g. to aggregate latencies across instances.
+    pub fn latency_histogram(&self) -> LatencyHistogram {
+        self.circuit_breaker.latency_histogram().clone()
+    }
+
+    /// Share of calls in the current window that were rejected by the
+    /// breaker, e.g. to let a load balancer weight instances by their
+    /// health.
+    pub fn rejection_rate(&mut self) -> f64 {
+        self.circuit_breaker.rejection_rate()
     }
 
     pub fn run(&mut self, param: I) -> Result<O, E> {
-        // Run the command if the breaker is disabled:
-        let enabled = self.circuit_breaker.config.circuit_breaker_enabled;
-        if !enabled {
-            return (self.cmd)(param);
-        }
-
-        // Execute the command if the breaker is enabled and execution
-        // is allowed.
-        let is_allowed = self.circuit_breaker.check_command_allowed();
-        if is_allowed {
-            let result = (self.cmd)(param);
-            self.circuit_breaker.register_result(&result);
-
-            return match result {
-                Ok(result) => Ok(result),
-                Err(err) => {
-                    // If a fallback is configured, use it on error:
-                    if let Some(ref fallback) = self.fallback {
-                        Ok(fallback(err))
-                    } else {
-                        Err(err)
-                    }
+        self.run_in_context(param, None, None, None)
+            .map(Degradable::into_inner)
+    }
+
+    /// Like `run`, but passes the given context (e.g. a request id) to
+    /// the `*_with_context` methods of all observers.
+    pub fn run_with_context<C: Any>(&mut self, param: I, context: C) -> Result<O, E> {
+        self.run_in_context(param, Some(&context), None, None)
+            .map(Degradable::into_inner)
+    }
+
+    /// Like `run`, but also records the outcome under the given tag
+    /// (e.g. the downstream region), see `metrics_by_tag`.
+    pub fn run_tagged(&mut self, param: I, tag: &'static str) -> Result<O, E> {
+        self.run_in_context(param, None, Some(tag), None)
+            .map(Degradable::into_inner)
+    }
+
+    fn run_in_context(
+        &mut self,
+        param: I,
+        context: Option<&dyn Any>,
+        tag: Option<&'static str>,
+        class: Option<OperationClass>,
+    ) -> Result<Degradable<O>, E> {
+        self.check_input(&param, context)?;
+        self.check_rate_limit(context)?;
+
+        if !self.admit(class, context) {
+            let result = self.reject(context);
+            return result.map(|value| Degradable::Degraded(value, DegradationReason::Rejected));
+        }
+
+        self.run_admitted(param, context, tag)
+    }
+
+    /// Runs a call that passed the checks of `run_in_context` and, if
+    /// the breaker is enabled, was allowed by it. Values provided by
+    /// the fallback are marked as degraded.
+    fn run_admitted(
+        &mut self,
+        param: I,
+        context: Option<&dyn Any>,
+        tag: Option<&'static str>,
+    ) -> Result<Degradable<O>, E> {
+        let result = if self.circuit_breaker.config.circuit_breaker_enabled {
+            self.call(param)
+        } else {
+            (self.cmd)(param)
+        };
+        self.complete(result, context, tag)
+    }
+
+    /// Records the result of an admitted call and applies `intercept`
+    /// and, to errors, the fallback. While the breaker is disabled,
+    /// nothing is recorded and the fallback isn't used, but observers
+    /// are still notified about the outcome.
+    fn complete(
+        &mut self,
+        result: Result<O, E>,
+        context: Option<&dyn Any>,
+        tag: Option<&'static str>,
+    ) -> Result<Degradable<O>, E> {
+        if !self.circuit_breaker.config.circuit_breaker_enabled {
+            self.finalize(self.outcome_of(&result), context);
+            return self.intercept_output(result).map(Degradable::Fresh);
+        }
+
+        self.register_result(&result, context);
+        if let Some(tag) = tag {
+            self.circuit_breaker.register_tagged_result(&result, tag);
+        }
+        let result = self.intercept_output(result);
+        let failed = result.is_err();
+        self.fallback_on_error(result).map(|value| {
+            if failed {
+                Degradable::Degraded(value, DegradationReason::Failed)
+            } else {
+                Degradable::Fresh(value)
+            }
+        })
+    }
+
+    /// Like `run`, but always runs the command, even if the circuit is
+    /// open or the breaker is disabled (e.g. for an admin override).
+    /// The outcome is still recorded, so the breaker's statistics stay
+    /// accurate.
+    pub fn run_bypass(&mut self, param: I) -> Result<O, E> {
+        self.check_input(&param, None)?;
+
+        let result = self.call(param);
+        self.register_result(&result, None);
+        let result = self.intercept_output(result);
+        self.fallback_on_error(result)
+    }
+
+    /// Like `run`, but also rejects the call while the error
+    /// percentage of the window reaches the threshold configured for
+    /// its class. This lets e.g. writes be shed earlier than reads,
+    /// based on the outcomes of both.
+    pub fn run_classified(&mut self, param: I, class: OperationClass) -> Result<O, E> {
+        self.run_in_context(param, None, None, Some(class))
+            .map(Degradable::into_inner)
+    }
+
+    /// Like `run`, but acquires the input (e.g. a pooled connection)
+    /// with the given function only once the breaker allows the call.
+    /// Calls rejected by an open circuit never acquire a resource, and
+    /// an acquired resource is dropped, returning it to its pool, when
+    /// the command function is done with it. Failures to acquire the
+    /// resource are recorded like failures of the command function, as
+    /// are resources rejected by `validate`.
+    pub fn run_with_resource<A>(&mut self, acquire: A) -> Result<O, E>
+    where
+        A: FnOnce() -> Result<I, E>,
+    {
+        self.check_rate_limit(None)?;
+
+        if !self.admit(None, None) {
+            return self.reject(None);
+        }
+
+        // The call was already admitted, so failures to acquire a
+        // resource, including invalid ones, are recorded like failures
+        // of the command function:
+        let result = match acquire() {
+            Ok(ref resource) if !self.is_valid(resource) => Err(E::from(CriusError::InvalidInput)),
+            Ok(resource) => {
+                return self
+                    .run_admitted(resource, None, None)
+                    .map(Degradable::into_inner)
+            }
+            Err(err) => Err(err),
+        };
+        self.complete(result, None, None)
+            .map(Degradable::into_inner)
+    }
+
+    /// Like `run`, but maps successes to `ControlFlow::Continue` and
+    /// errors, including rejections, to `ControlFlow::Break`. This lets
+    /// breaker calls be chained with `?` in functions returning
+    /// `ControlFlow`.
+    pub fn run_control(&mut self, param: I) -> ControlFlow<E, O> {
+        match self.run(param) {
+            Ok(result) => ControlFlow::Continue(result),
+            Err(err) => ControlFlow::Break(err),
+        }
+    }
+
+    /// Like `run`, but marks values provided by the fallback as
+    /// `Degradable::Degraded`, so callers can e.g. avoid caching them.
+    /// Values of the command function are `Degradable::Fresh`.
+    pub fn run_degradable(&mut self, param: I) -> Result<Degradable<O>, E> {
+        self.run_in_context(param, None, None, None)
+    }
+
+    /// Like `run`, but pairs errors with the state the breaker was in
+    /// for the call. Calls rejected by the breaker, be it by an open
+    /// circuit or by shedding, are paired with `CircuitState::Open`.
+    /// All other errors are paired with `CircuitState::Closed`, even if
+    /// the failure opened the circuit (e.g. a failed probe), so callers
+    /// can tell rejections apart from failures of the command function
+    /// without inspecting the error.
+    pub fn run_with_state(&mut self, param: I) -> Result<O, (E, CircuitState)> {
+        let closed = |err| (err, CircuitState::Closed);
+        self.check_input(&param, None).map_err(closed)?;
+        self.check_rate_limit(None).map_err(closed)?;
+
+        if !self.admit(None, None) {
+            return self.reject(None).map_err(|err| (err, CircuitState::Open));
+        }
+
+        self.run_admitted(param, None, None)
+            .map(Degradable::into_inner)
+            .map_err(closed)
+    }
+
+    /// Calls the command function, recording the time the call took
+    /// and notifying observers about it.
+    fn call(&mut self, param: I) -> Result<O, E> {
+        let start = self.circuit_breaker.now();
+        let result = (self.cmd)(param);
+        let latency = self.circuit_breaker.now().saturating_duration_since(start);
+        self.record_latency(latency);
+        result
+    }
+
+    /// Returns the outcome observers are notified about for a result
+    /// of the command function.
+    fn outcome_of(&self, result: &Result<O, E>) -> Outcome {
+        match *result {
+            Err(ref err) if self.is_failure(err) => Outcome::Failure,
+            _ => Outcome::Success,
+        }
+    }
+
+    fn record_latency(&mut self, latency: Duration) {
+        self.circuit_breaker.register_latency(latency);
+        for observer in &self.observers {
+            observer.on_latency(latency);
+        }
+    }
+
+    fn check_input(&self, param: &I, context: Option<&dyn Any>) -> Result<(), E> {
+        if self.is_valid(param) {
+            return Ok(());
+        }
+        self.finalize(Outcome::InvalidInput, context);
+        Err(E::from(CriusError::InvalidInput))
+    }
+
+    fn is_valid(&self, param: &I) -> bool {
+        match self.validate {
+            Some(validate) => validate(param),
+            None => true,
+        }
+    }
+
+    fn check_rate_limit(&self, context: Option<&dyn Any>) -> Result<(), E> {
+        match self.rate_limit {
+            Some(rate_limit) if !rate_limit() => {
+                self.finalize(Outcome::RateLimited, context);
+                Err(E::from(CriusError::RateLimited))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Notifies observers about the final outcome of a call. Every
+    /// path through the run methods ends here exactly once, be it
+    /// through `register_result`, `reject`, `check_input`,
+    /// `check_rate_limit` or, while the breaker is disabled,
+    /// `complete`.
+    fn finalize(&self, outcome: Outcome, context: Option<&dyn Any>) {
+        for observer in &self.observers {
+            match (outcome, context) {
+                (Outcome::Success, Some(context)) => observer.on_success_with_context(context),
+                (Outcome::Success, None) => observer.on_success(),
+                (Outcome::Failure, Some(context)) => observer.on_failure_with_context(context),
+                (Outcome::Failure, None) => observer.on_failure(),
+                (Outcome::Rejected, Some(context)) => observer.on_rejected_with_context(context),
+                (Outcome::Rejected, None) => observer.on_rejected(),
+                (Outcome::InvalidInput, _) | (Outcome::RateLimited, _) => (),
+            }
+
+            match context {
+                Some(context) => observer.on_outcome_with_context(outcome, context),
+                None => observer.on_outcome(outcome),
+            }
+        }
+    }
+
+    /// Whether the breaker admits the call, which it always does
+    /// while disabled. Calls of a class are also subject to the
+    /// class's threshold, see `run_classified`.
+    fn admit(&mut self, class: Option<OperationClass>, context: Option<&dyn Any>) -> bool {
+        if !self.circuit_breaker.config.circuit_breaker_enabled {
+            return true;
+        }
+
+        let previous_state = self.circuit_breaker.state();
+        let is_allowed = match class {
+            Some(class) => self.circuit_breaker.check_class_allowed(class),
+            None => self.circuit_breaker.check_command_allowed(),
+        };
+        self.notify_state_change(previous_state, context);
+        self.notify_decision(is_allowed);
+        is_allowed
+    }
+
+    fn notify_decision(&self, is_allowed: bool) {
+        let on_decision = match self.on_decision {
+            Some(on_decision) => on_decision,
+            None => return,
+        };
+
+        let decision = if is_allowed {
+            Decision::Allowed
+        } else if self.circuit_breaker.state() == CircuitState::Open {
+            Decision::Rejected(RejectionReason::CircuitOpen)
+        } else {
+            Decision::Rejected(RejectionReason::Shed)
+        };
+        on_decision(decision);
+    }
+
+    fn notify_state_change(&self, previous_state: CircuitState, context: Option<&dyn Any>) {
+        let state = self.circuit_breaker.state();
+        if state != previous_state {
+            for observer in &self.observers {
+                match context {
+                    Some(context) => observer.on_state_change_with_context(state, context),
+                    None => observer.on_state_change(state),
                 }
-            };
+            }
+        }
+    }
+
+    fn register_result(&mut self, result: &Result<O, E>, context: Option<&dyn Any>) {
+        // Errors the classifier doesn't consider failures are
+        // recorded like successes:
+        let failure = match *result {
+            Ok(_) => None,
+            Err(ref err) if self.is_failure(err) => Some(err),
+            Err(_) => None,
+        };
+        let weight = match (failure, self.failure_weight) {
+            (Some(err), Some(failure_weight)) => failure_weight(err),
+            _ => 1.0,
+        };
+        let weight = match self.escalation {
+            Some(ref mut escalation) => weight * escalation(failure),
+            None => weight,
+        };
+        let previous_state = self.circuit_breaker.state();
+        let recorded = match failure {
+            Some(_) => Err(()),
+            None => Ok(()),
+        };
+        self.circuit_breaker
+            .register_weighted_result(&recorded, weight);
+
+        let outcome = if recorded.is_ok() {
+            Outcome::Success
+        } else {
+            Outcome::Failure
+        };
+        self.finalize(outcome, context);
+
+        // A failed probe or a burst of failures opens the breaker
+        // right away:
+        self.notify_state_change(previous_state, context);
+    }
+
+    fn is_failure(&self, err: &E) -> bool {
+        let classify_failure = match self.classify_failure {
+            Some(classify_failure) => classify_failure,
+            None => return true,
+        };
+
+        let classified = panic::catch_unwind(AssertUnwindSafe(|| classify_failure(err)))
+            .unwrap_or_else(|_| Err(ClassifierError::new("classifier panicked")));
+        match classified {
+            Ok(classification) => classification == Classification::Failure,
+            Err(error) => {
+                for observer in &self.observers {
+                    observer.on_classifier_error(&error);
+                }
+                true
+            }
+        }
+    }
+
+    fn intercept_output(&self, result: Result<O, E>) -> Result<O, E> {
+        match self.intercept {
+            Some(intercept) => result.map(intercept),
+            None => result,
         }
+    }
+
+    fn fallback_on_error(&mut self, result: Result<O, E>) -> Result<O, E> {
+        let result = match result {
+            Ok(result) => Ok(result),
+            Err(err) => {
+                // If a fallback is configured and applies to the
+                // error, use it:
+                let applies = match self.fallback_predicate {
+                    Some(fallback_predicate) => fallback_predicate(&err),
+                    None => true,
+                };
+                match self.fallback {
+                    Some(ref fallback) if applies => call_fallback(fallback, err),
+                    _ => Err(err),
+                }
+            }
+        };
+
+        self.circuit_breaker.register_user_visible_result(&result);
+        result
+    }
+
+    fn reject(&mut self, context: Option<&dyn Any>) -> Result<O, E> {
+        self.finalize(Outcome::Rejected, context);
 
         // If execution is rejected, either run the configured
-        // fallback (if present) or propagate the rejection as an
-        // error:
+        // fallback (if present, not overridden by the policy and
+        // within the fallback rate limit) or propagate the rejection
+        // as an error:
         let err = E::from(CriusError::ExecutionRejected);
-        if let Some(ref fallback) = self.fallback {
-            return Ok(fallback(err));
-        } else {
-            return Err(err);
+        let prefer_error = self.circuit_breaker.config.prefer_error_on_open;
+        let result = match self.fallback {
+            Some(ref fallback) if !prefer_error && self.circuit_breaker.take_fallback() => {
+                call_fallback(fallback, err)
+            }
+            _ => Err(err),
+        };
+
+        self.circuit_breaker.register_user_visible_result(&result);
+        result
+    }
+}
+
+/// Calls the fallback, turning a panic of the fallback into
+/// `CriusError::FallbackPanicked` instead of unwinding through `run`.
+fn call_fallback<O, E, FB>(fallback: &FB, err: E) -> Result<O, E>
+where
+    E: From<CriusError>,
+    FB: Fn(E) -> O,
+{
+    panic::catch_unwind(AssertUnwindSafe(|| fallback(err)))
+        .map_err(|_| E::from(CriusError::FallbackPanicked))
+}
+
+impl<I, O, E, F, FB> Command<I, O, E, F, FB>
+where
+    E: From<CriusError> + PartialEq + Clone + Send + 'static,
+    F: Fn(I) -> Result<O, E>,
+    FB: Fn(E) -> O,
+{
+    /// Escalate the weight of failures repeating the previous failure's
+    /// error. Every immediately preceding identical error multiplies
+    /// the weight by `factor`, so a persistent error (e.g. a rejected
+    /// credential) trips the breaker sooner than varied transient
+    /// errors. A success or a different error ends the escalation.
+    pub fn escalate_repeated_errors(&mut self, factor: f64) -> &mut Self {
+        let mut last_error: Option<E> = None;
+        let mut repeats = 0;
+        self.escalation = Some(Box::new(move |err: Option<&E>| {
+            repeats = match (err, &last_error) {
+                (Some(err), Some(last_error)) if err == last_error => repeats + 1,
+                _ => 0,
+            };
+            last_error = err.cloned();
+            factor.powi(repeats)
+        }));
+        self
+    }
+}
+
+impl<I, O, E, F, FB> Command<I, O, E, F, FB>
+where
+    I: Clone,
+    E: From<CriusError>,
+    F: Fn(I) -> Result<O, E>,
+    FB: Fn(E) -> O,
+{
+    /// Like `run`, but also runs the secondary command with a clone
+    /// of the input and discards its result, e.g. to compare the
+    /// health of a new backend with the current one before switching
+    /// over. Each command records its outcomes in its own window.
+    pub fn run_mirrored<F2, FB2>(
+        &mut self,
+        param: I,
+        secondary: &mut Command<I, O, E, F2, FB2>,
+    ) -> Result<O, E>
+    where
+        F2: Fn(I) -> Result<O, E>,
+        FB2: Fn(E) -> O,
+    {
+        let mirrored = param.clone();
+        let result = self.run(param);
+        let _ = secondary.run(mirrored);
+        result
+    }
+
+    /// Like `run`, but retries failed calls up to `max_retries` times.
+    /// Only the outcome of the final attempt is recorded by the
+    /// breaker.
+    pub fn run_with_retries(&mut self, param: I) -> Result<O, E> {
+        self.run_traced(param).0
+    }
+
+    /// Like `run_with_retries`, but also returns a record of every
+    /// attempt that was made. The trace is empty if the call was
+    /// rejected due to invalid input, by the rate limit or by an open
+    /// breaker.
+    pub fn run_traced(&mut self, param: I) -> (Result<O, E>, Vec<AttemptRecord>) {
+        if let Err(err) = self
+            .check_input(&param, None)
+            .and_then(|_| self.check_rate_limit(None))
+        {
+            return (Err(err), Vec::new());
         }
+
+        if !self.admit(None, None) {
+            return (self.reject(None), Vec::new());
+        }
+
+        let enabled = self.circuit_breaker.config.circuit_breaker_enabled;
+
+        let max_attempts = self.circuit_breaker.config.max_retries.saturating_add(1);
+        let mut attempts = Vec::new();
+        let result = loop {
+            let attempt = attempts.len() as u32 + 1;
+            let start = self.circuit_breaker.now();
+            let result = (self.cmd)(param.clone());
+            let latency = self.circuit_breaker.now().saturating_duration_since(start);
+            if enabled {
+                self.record_latency(latency);
+            }
+            attempts.push(AttemptRecord {
+                attempt,
+                outcome: if result.is_ok() {
+                    Point::SUCCESS
+                } else {
+                    Point::FAILURE
+                },
+                latency,
+            });
+
+            if result.is_ok() || attempt >= max_attempts {
+                break result;
+            }
+        };
+
+        let result = self.complete(result, None, None);
+        (result.map(Degradable::into_inner), attempts)
     }
 }
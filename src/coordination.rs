@@ -0,0 +1,19 @@
+use circuit_breaker::Metrics;
+use std::fmt::Debug;
+
+/// A coordination sink connects a breaker to an external coordinator
+/// (e.g. backed by Redis or a gossip protocol) that aggregates the
+/// health of a whole fleet of breakers.
+///
+/// The sink is consulted before every call. It receives the breaker's
+/// local metrics and may override the local decision.
+pub trait CoordinationSink: Debug + Send + Sync {
+    /// Report the local metrics of the breaker to the coordinator.
+    fn report(&self, metrics: Metrics);
+
+    /// Returns `Some(true)` to open the circuit and `Some(false)` to
+    /// close it regardless of the local statistics. Returns `None` to
+    /// let the breaker decide locally, which closes a circuit the
+    /// coordinator forced open.
+    fn fleet_should_open(&self) -> Option<bool>;
+}
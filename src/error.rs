@@ -3,6 +3,10 @@ use std::error::Error;
 
 /// This error type describes the possible failures that can occur
 /// while attempting to run a circuit breaker command.
+///
+/// New variants may be added in the future, so matches on this type
+/// need a wildcard arm.
+#[non_exhaustive]
 pub enum CriusError {
     /// Error variant returned in case of an open breaker.
     ExecutionRejected,
@@ -10,16 +14,33 @@ pub enum CriusError {
     /// Error variant returned in case of invalid configuration (e.g.
     /// parameters that cause duration calculations to overflow).
     InvalidConfig,
+
+    /// Error variant returned if the input to a command was rejected
+    /// by its validation function.
+    InvalidInput,
+
+    /// Error variant returned if the fallback panicked.
+    FallbackPanicked,
+
+    /// Error variant returned if the call was rejected by the rate
+    /// limit of a command before the breaker was consulted.
+    RateLimited,
 }
 
 const REJECTED: &str = "Rejected command execution due to open breaker";
 const INVALID: &str = "Provided circuit breaker configuration was invalid";
+const INVALID_INPUT: &str = "Rejected command execution due to invalid input";
+const FALLBACK_PANICKED: &str = "Fallback of the circuit breaker command panicked";
+const RATE_LIMITED: &str = "Rejected command execution due to rate limit";
 
 impl fmt::Display for CriusError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             CriusError::ExecutionRejected => write!(f, "{}", REJECTED),
             CriusError::InvalidConfig => write!(f, "{}", INVALID),
+            CriusError::InvalidInput => write!(f, "{}", INVALID_INPUT),
+            CriusError::FallbackPanicked => write!(f, "{}", FALLBACK_PANICKED),
+            CriusError::RateLimited => write!(f, "{}", RATE_LIMITED),
         }
     }
 }
@@ -35,6 +56,33 @@ impl Error for CriusError {
         match *self {
             CriusError::ExecutionRejected => REJECTED,
             CriusError::InvalidConfig => INVALID,
+            CriusError::InvalidInput => INVALID_INPUT,
+            CriusError::FallbackPanicked => FALLBACK_PANICKED,
+            CriusError::RateLimited => RATE_LIMITED,
+        }
+    }
+}
+
+/// Error of a failure classifier, see `Command::classify_failure`.
+/// Calls whose error could not be classified are recorded as
+/// failures.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ClassifierError {
+    pub message: String,
+}
+
+impl ClassifierError {
+    pub fn new<M: Into<String>>(message: M) -> ClassifierError {
+        ClassifierError {
+            message: message.into(),
         }
     }
 }
+
+impl fmt::Display for ClassifierError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Failed to classify error: {}", self.message)
+    }
+}
+
+impl Error for ClassifierError {}
@@ -0,0 +1,169 @@
+use atomic_window::AtomicWindow;
+use clock::{Clock, SystemClock};
+use command::{BreakerMode, Config};
+use error::CriusError;
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use window::Point;
+
+/// A command that does not allocate once it has been defined. It
+/// records outcomes in an `AtomicWindow`, whose counters are allocated
+/// up front, instead of a window of points.
+///
+/// In exchange, `FastCommand` only supports the basic breaker: there
+/// are no observers, failure weights, coordination, retries or
+/// user-visible statistics. Use `Command` if any of these are needed.
+///
+/// Of the configuration, only the thresholds, the window,
+/// `circuit_open_ms`, `circuit_breaker_enabled` and
+/// `prefer_error_on_open` are supported. Defining a command fails with
+/// `CriusError::InvalidConfig` if any option changing how the circuit
+/// opens or closes is set, e.g. `mode`, `latch_open`, `warmup_ms`,
+/// `probe_budget` or `recovery_ramp`, or if `circuit_open_ms` is below
+/// `open_duration_floor_ms`. Options without effect on a command that
+/// only runs calls (e.g. `max_retries`) are ignored.
+///
+/// `run` and `is_open` are allocation-free, provided the command and
+/// fallback functions don't allocate themselves.
+pub struct FastCommand<I, O, E, F, FB>
+where
+    E: From<CriusError>,
+    F: Fn(I) -> Result<O, E>,
+    FB: Fn(E) -> O,
+{
+    pub cmd: F,
+    pub fallback: Option<FB>,
+    phantom_data: PhantomData<I>,
+    window: AtomicWindow,
+    circuit_open_time: Option<Instant>,
+    config: Config,
+    clock: Arc<dyn Clock>,
+}
+
+impl<I, O, E, F, FB> FastCommand<I, O, E, F, FB>
+where
+    E: From<CriusError>,
+    F: Fn(I) -> Result<O, E>,
+    FB: Fn(E) -> O,
+{
+    pub fn define(cfg: Config, cmd: F) -> Result<FastCommand<I, O, E, F, FB>, CriusError> {
+        cfg.validate()?;
+        if !is_supported(&cfg) {
+            return Err(CriusError::InvalidConfig);
+        }
+        let clock: Arc<dyn Clock> = Arc::new(SystemClock);
+        let window =
+            AtomicWindow::with_clock(cfg, clock.clone()).ok_or(CriusError::InvalidConfig)?;
+
+        Ok(FastCommand {
+            cmd,
+            fallback: None,
+            phantom_data: PhantomData,
+            window,
+            circuit_open_time: None,
+            config: cfg,
+            clock,
+        })
+    }
+
+    pub fn define_with_fallback(
+        cfg: Config,
+        cmd: F,
+        fallback: FB,
+    ) -> Result<FastCommand<I, O, E, F, FB>, CriusError> {
+        let mut command = FastCommand::define(cfg, cmd)?;
+        command.fallback = Some(fallback);
+        Ok(command)
+    }
+
+    /// Read the current time from the given clock instead of the
+    /// system time, e.g. to control time in tests. This clears the
+    /// window, see `AtomicWindow::set_clock`.
+    pub fn clock(&mut self, clock: Arc<dyn Clock>) -> &mut Self {
+        self.window.set_clock(clock.clone());
+        self.clock = clock;
+        self
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.circuit_open_time.is_some()
+    }
+
+    pub fn run(&mut self, param: I) -> Result<O, E> {
+        if !self.config.circuit_breaker_enabled {
+            return (self.cmd)(param);
+        }
+
+        if !self.check_command_allowed() {
+            let err = E::from(CriusError::ExecutionRejected);
+            return match self.fallback {
+                Some(ref fallback) if !self.config.prefer_error_on_open => Ok(fallback(err)),
+                _ => Err(err),
+            };
+        }
+
+        match (self.cmd)(param) {
+            Ok(result) => {
+                self.window.add_point(Point::SUCCESS);
+                Ok(result)
+            }
+            Err(err) => {
+                self.window.add_point(Point::FAILURE);
+                match self.fallback {
+                    Some(ref fallback) => Ok(fallback(err)),
+                    None => Err(err),
+                }
+            }
+        }
+    }
+
+    fn check_command_allowed(&mut self) -> bool {
+        if let Some(open_time) = self.circuit_open_time {
+            let open_for = self.clock.now().saturating_duration_since(open_time);
+            if open_for < Duration::from_millis(self.config.circuit_open_ms) {
+                return false;
+            }
+            self.circuit_open_time = None;
+            true
+        } else if self.should_open_circuit() {
+            self.circuit_open_time = Some(self.clock.now());
+            self.window.clear_window();
+            false
+        } else {
+            true
+        }
+    }
+
+    fn should_open_circuit(&self) -> bool {
+        let error_nr = u64::from(self.window.error_nr());
+        let total_nr = error_nr + u64::from(self.window.success_nr());
+        if error_nr == 0 {
+            return false;
+        }
+
+        let error_percentage = (error_nr * 100 / total_nr) as i32;
+        let pct_above_threshold = error_percentage >= self.config.error_threshold_percentage;
+        let count_above_threshold = error_nr as i64 >= i64::from(self.config.error_threshold);
+
+        pct_above_threshold && count_above_threshold
+    }
+}
+
+/// Whether `FastCommand` honours every option of the configuration
+/// that changes how the circuit opens or closes.
+fn is_supported(cfg: &Config) -> bool {
+    cfg.mode == BreakerMode::Rolling
+        && !cfg.adaptive_open_duration
+        && !cfg.graduated_shedding
+        && !cfg.latch_open
+        && cfg.burst_threshold.is_none()
+        && cfg.probe_budget.is_none()
+        && cfg.count_window_buckets.is_none()
+        && cfg.warmup_ms.is_none()
+        && cfg.latency_trend_guard.is_none()
+        && cfg.max_total_calls.is_none()
+        && cfg.recovery_ramp.is_none()
+        && cfg.max_fallback_rate.is_none()
+        && cfg.circuit_open_ms >= cfg.open_duration_floor_ms
+}
@@ -0,0 +1,157 @@
+//! Aggregated health of all registered breakers, e.g. for a `/healthz`
+//! endpoint. Breakers are registered by adding the observer returned
+//! by `register` to their command:
+//!
+//! ```
+//! use crius::command::{Command, Config};
+//! use crius::error::CriusError;
+//! use crius::health;
+//!
+//! let mut cmd = Command::<(), (), CriusError, _, fn(CriusError)>::define(
+//!     Config::default(),
+//!     |_| Ok(()),
+//! ).unwrap();
+//! cmd.add_observer(health::register("database", true));
+//!
+//! let report = health::report_all();
+//! assert!(report.healthy);
+//! println!("{}", report.to_json());
+//! ```
+//!
+//! The service is unhealthy while the circuit of any critical breaker
+//! is open. Breakers are unregistered when their observer is dropped,
+//! i.e. along with their command.
+
+use circuit_breaker::CircuitState;
+use observer::Observer;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+static REGISTRY: Mutex<Vec<Arc<Entry>>> = Mutex::new(Vec::new());
+
+#[derive(Debug)]
+struct Entry {
+    name: String,
+    critical: bool,
+    open: AtomicBool,
+}
+
+/// Register a breaker under the given name. If `critical` is set, the
+/// service is reported as unhealthy while the breaker's circuit is
+/// open. The returned observer tracks the breaker's state and has to
+/// be added to its command.
+pub fn register(name: &str, critical: bool) -> HealthObserver {
+    let entry = Arc::new(Entry {
+        name: name.to_string(),
+        critical,
+        open: AtomicBool::new(false),
+    });
+    REGISTRY.lock().unwrap().push(entry.clone());
+    HealthObserver { entry }
+}
+
+/// Returns the state of all registered breakers.
+pub fn report_all() -> HealthReport {
+    let breakers: Vec<BreakerHealth> = REGISTRY
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|entry| BreakerHealth {
+            name: entry.name.clone(),
+            critical: entry.critical,
+            state: if entry.open.load(Ordering::Acquire) {
+                CircuitState::Open
+            } else {
+                CircuitState::Closed
+            },
+        })
+        .collect();
+    let healthy = breakers
+        .iter()
+        .all(|breaker| !breaker.critical || breaker.state == CircuitState::Closed);
+
+    HealthReport { healthy, breakers }
+}
+
+/// Observer keeping the state of a registered breaker up to date, see
+/// `register`. Dropping it unregisters the breaker.
+#[derive(Debug)]
+pub struct HealthObserver {
+    entry: Arc<Entry>,
+}
+
+impl Observer for HealthObserver {
+    fn on_state_change(&self, state: CircuitState) {
+        self.entry
+            .open
+            .store(state == CircuitState::Open, Ordering::Release);
+    }
+}
+
+impl Drop for HealthObserver {
+    fn drop(&mut self) {
+        if let Ok(mut registry) = REGISTRY.lock() {
+            registry.retain(|entry| !Arc::ptr_eq(entry, &self.entry));
+        }
+    }
+}
+
+/// Health of all registered breakers, see `report_all`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct HealthReport {
+    /// Whether the circuits of all critical breakers are closed
+    pub healthy: bool,
+
+    /// State of every registered breaker, in registration order
+    pub breakers: Vec<BreakerHealth>,
+}
+
+/// State of a registered breaker, see `HealthReport`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct BreakerHealth {
+    pub name: String,
+    pub critical: bool,
+    pub state: CircuitState,
+}
+
+impl HealthReport {
+    /// Serialize the report as JSON, e.g.
+    /// `{"healthy":true,"breakers":[{"name":"database","critical":true,"state":"closed"}]}`.
+    pub fn to_json(&self) -> String {
+        let mut json = format!("{{\"healthy\":{},\"breakers\":[", self.healthy);
+        for (i, breaker) in self.breakers.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            let state = match breaker.state {
+                CircuitState::Closed => "closed",
+                CircuitState::Open => "open",
+            };
+            json.push_str("{\"name\":");
+            write_json_string(&mut json, &breaker.name);
+            let _ = write!(
+                json,
+                ",\"critical\":{},\"state\":\"{}\"}}",
+                breaker.critical, state
+            );
+        }
+        json.push_str("]}");
+        json
+    }
+}
+
+fn write_json_string(json: &mut String, value: &str) {
+    json.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(json, "\\u{:04x}", c as u32);
+            }
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+}
@@ -0,0 +1,81 @@
+//! Instruments recorded by all integrations with metrics libraries.
+//! Each integration names them in its own convention and labels them
+//! with the name of the breaker.
+
+use circuit_breaker::CircuitState;
+use observer::Outcome;
+
+pub(crate) struct Instrument {
+    /// Name in the dotted convention of OpenTelemetry and the `metrics`
+    /// facade
+    #[cfg(any(feature = "metrics", feature = "opentelemetry"))]
+    pub name: &'static str,
+
+    /// Name in the convention of Prometheus
+    #[cfg(feature = "prometheus")]
+    pub prometheus_name: &'static str,
+
+    #[cfg(any(feature = "opentelemetry", feature = "prometheus"))]
+    pub description: &'static str,
+}
+
+/// Counters of the outcomes of calls, see `counter_index`
+pub(crate) const COUNTERS: [Instrument; 3] = [
+    Instrument {
+        #[cfg(any(feature = "metrics", feature = "opentelemetry"))]
+        name: "crius.successes",
+        #[cfg(feature = "prometheus")]
+        prometheus_name: "crius_successes_total",
+        #[cfg(any(feature = "opentelemetry", feature = "prometheus"))]
+        description: "Number of successful calls",
+    },
+    Instrument {
+        #[cfg(any(feature = "metrics", feature = "opentelemetry"))]
+        name: "crius.failures",
+        #[cfg(feature = "prometheus")]
+        prometheus_name: "crius_failures_total",
+        #[cfg(any(feature = "opentelemetry", feature = "prometheus"))]
+        description: "Number of failed calls",
+    },
+    Instrument {
+        #[cfg(any(feature = "metrics", feature = "opentelemetry"))]
+        name: "crius.rejections",
+        #[cfg(feature = "prometheus")]
+        prometheus_name: "crius_rejections_total",
+        #[cfg(any(feature = "opentelemetry", feature = "prometheus"))]
+        description: "Number of calls rejected by an open breaker",
+    },
+];
+
+/// Gauge of the breaker state, see `state_value`
+pub(crate) const STATE: Instrument = Instrument {
+    #[cfg(any(feature = "metrics", feature = "opentelemetry"))]
+    name: "crius.state",
+    #[cfg(feature = "prometheus")]
+    prometheus_name: "crius_state",
+    #[cfg(any(feature = "opentelemetry", feature = "prometheus"))]
+    description: "State of the breaker, 0 if closed and 1 if open",
+};
+
+/// Label every instrument carries, set to the name of the breaker
+pub(crate) const BREAKER_LABEL: &str = "breaker";
+
+/// Returns the index of the counter in `COUNTERS` incremented for the
+/// given outcome. Calls rejected due to invalid input or by the rate
+/// limit are not counted.
+pub(crate) fn counter_index(outcome: Outcome) -> Option<usize> {
+    match outcome {
+        Outcome::Success => Some(0),
+        Outcome::Failure => Some(1),
+        Outcome::Rejected => Some(2),
+        _ => None,
+    }
+}
+
+/// Returns the value of the state gauge for the given state.
+pub(crate) fn state_value(state: CircuitState) -> u8 {
+    match state {
+        CircuitState::Closed => 0,
+        CircuitState::Open => 1,
+    }
+}
@@ -0,0 +1,72 @@
+use std::time::Duration;
+
+/// Number of buckets of a histogram, including the unbounded last one
+const BUCKETS: usize = 32;
+
+/// Histogram of latencies with exponentially growing buckets. The
+/// first bucket counts latencies below 1µs, every following bucket
+/// ends at twice the end of the previous one, and the last bucket
+/// counts all latencies of at least 2^30µs (about 18 minutes).
+///
+/// All histograms share the same bucket boundaries, so histograms of
+/// different breakers or instances can be merged by adding up their
+/// counts, unlike percentiles.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct LatencyHistogram {
+    counts: [u64; BUCKETS],
+}
+
+impl LatencyHistogram {
+    pub fn new() -> LatencyHistogram {
+        LatencyHistogram {
+            counts: [0; BUCKETS],
+        }
+    }
+
+    pub fn record(&mut self, latency: Duration) {
+        let index = bucket_index(latency);
+        self.counts[index] = self.counts[index].saturating_add(1);
+    }
+
+    /// Add the counts of another histogram to this one.
+    pub fn merge(&mut self, other: &LatencyHistogram) {
+        for (count, other_count) in self.counts.iter_mut().zip(other.counts.iter()) {
+            *count = count.saturating_add(*other_count);
+        }
+    }
+
+    /// Total number of recorded latencies
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// Returns the exclusive upper bound of every bucket along with
+    /// the number of latencies recorded in it. The last bucket has no
+    /// upper bound.
+    pub fn buckets<'a>(&'a self) -> impl Iterator<Item = (Option<Duration>, u64)> + 'a {
+        self.counts
+            .iter()
+            .enumerate()
+            .map(|(index, &count)| (upper_bound(index), count))
+    }
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> LatencyHistogram {
+        LatencyHistogram::new()
+    }
+}
+
+fn bucket_index(latency: Duration) -> usize {
+    let micros = latency.as_micros();
+    let index = (128 - micros.leading_zeros()) as usize;
+    index.min(BUCKETS - 1)
+}
+
+fn upper_bound(index: usize) -> Option<Duration> {
+    if index >= BUCKETS - 1 {
+        None
+    } else {
+        Some(Duration::from_micros(1 << index))
+    }
+}
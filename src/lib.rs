@@ -17,15 +17,47 @@
 //!
 //! [function pointers]: https://doc.rust-lang.org/book/second-edition/ch19-05-advanced-functions-and-closures.html#function-pointers
 
-mod circuit_breaker;
-mod circuit_breaker_stats;
-mod window;
+#[cfg(feature = "metrics")]
+extern crate metrics;
+#[cfg(feature = "opentelemetry")]
+extern crate opentelemetry;
+#[cfg(feature = "prometheus")]
+extern crate prometheus;
 
+pub mod atomic_window;
+pub mod circuit_breaker;
+pub mod circuit_breaker_stats;
+pub mod clock;
 pub mod command;
+pub mod coordination;
 pub mod error;
+pub mod fast_command;
+pub mod health;
+#[cfg(any(feature = "metrics", feature = "opentelemetry", feature = "prometheus"))]
+mod instruments;
+pub mod latency_histogram;
+#[cfg(feature = "metrics")]
+pub mod metrics_facade;
+pub mod observer;
+#[cfg(feature = "opentelemetry")]
+pub mod otel;
+#[cfg(feature = "prometheus")]
+pub mod prometheus_metrics;
+pub mod rng;
+#[cfg(feature = "sim")]
+pub mod sim;
+pub mod window;
 
 pub use command::Config;
 pub use error::CriusError;
+pub use observer::Observer;
+
+use clock::Clock;
+use command::{ClassifyFn, Decision};
+use coordination::CoordinationSink;
+use rng::Rng;
+use std::sync::{Arc, Mutex};
+use window::Window;
 
 /// Convenience type alias for function pointers matching the
 /// input/output and error types of a circuit breaker.
@@ -126,3 +158,186 @@ where
 {
     command::Command::define_with_fallback(config, function, fallback)
 }
+
+/// Use this function to construct a circuit breaker step by step,
+/// including any of the optional hooks of a `Command`. See
+/// `CommandBuilder` for the available options.
+///
+/// # Example:
+///
+/// ```
+/// # use crius::{builder, Config, CriusError};
+/// # #[derive(PartialEq, Debug)]
+/// # struct ExampleError;
+/// # impl From<CriusError> for ExampleError {
+/// #   fn from(_: CriusError) -> Self { ExampleError }
+/// # }
+/// let config = *Config::default().error_threshold(5).max_retries(2);
+///
+/// let mut cmd = builder(config)
+///     .command(|n: u32| if n > 10 { Err(ExampleError) } else { Ok(n * 2) })
+///     .fallback(|_err| 4)
+///     .validate(|n| *n != 0)
+///     .intercept(|n| n + 1)
+///     .build()
+///     .unwrap();
+///
+/// assert_eq!(Ok(21), cmd.run(10));
+/// assert_eq!(Ok(4), cmd.run(11));
+/// assert_eq!(Err(ExampleError), cmd.run(0));
+/// ```
+pub fn builder<I, O, E>(config: Config) -> CommandBuilder<I, O, E>
+where
+    E: From<CriusError>,
+{
+    CommandBuilder {
+        config,
+        function: None,
+        fallback: None,
+        failure_weight: None,
+        validate: None,
+        intercept: None,
+        fallback_predicate: None,
+        on_decision: None,
+        rate_limit: None,
+        classify_failure: None,
+        clock: None,
+        rng: None,
+        coordination: None,
+        shared_window: None,
+        observers: Vec::new(),
+    }
+}
+
+/// Collects the function, fallback and optional hooks of a circuit
+/// breaker, see `builder`. Every option corresponds to the setter of
+/// the same name on `Command`, except `escalate_repeated_errors`,
+/// which needs further bounds on the error type and is only available
+/// on the built command.
+pub struct CommandBuilder<I, O, E>
+where
+    E: From<CriusError>,
+{
+    config: Config,
+    function: Option<CommandFn<I, O, E>>,
+    fallback: Option<FallbackFn<O, E>>,
+    failure_weight: Option<fn(&E) -> f64>,
+    validate: Option<fn(&I) -> bool>,
+    intercept: Option<fn(O) -> O>,
+    fallback_predicate: Option<fn(&E) -> bool>,
+    on_decision: Option<fn(Decision)>,
+    rate_limit: Option<fn() -> bool>,
+    classify_failure: Option<ClassifyFn<E>>,
+    clock: Option<Arc<dyn Clock>>,
+    rng: Option<Arc<dyn Rng>>,
+    coordination: Option<Arc<dyn CoordinationSink>>,
+    shared_window: Option<Arc<Mutex<Window>>>,
+    observers: Vec<Box<dyn Observer + Send>>,
+}
+
+impl<I, O, E> CommandBuilder<I, O, E>
+where
+    E: From<CriusError>,
+{
+    pub fn command(mut self, function: CommandFn<I, O, E>) -> Self {
+        self.function = Some(function);
+        self
+    }
+
+    pub fn fallback(mut self, fallback: FallbackFn<O, E>) -> Self {
+        self.fallback = Some(fallback);
+        self
+    }
+
+    pub fn failure_weight(mut self, failure_weight: fn(&E) -> f64) -> Self {
+        self.failure_weight = Some(failure_weight);
+        self
+    }
+
+    pub fn validate(mut self, validate: fn(&I) -> bool) -> Self {
+        self.validate = Some(validate);
+        self
+    }
+
+    pub fn intercept(mut self, intercept: fn(O) -> O) -> Self {
+        self.intercept = Some(intercept);
+        self
+    }
+
+    pub fn fallback_predicate(mut self, fallback_predicate: fn(&E) -> bool) -> Self {
+        self.fallback_predicate = Some(fallback_predicate);
+        self
+    }
+
+    pub fn on_decision(mut self, on_decision: fn(Decision)) -> Self {
+        self.on_decision = Some(on_decision);
+        self
+    }
+
+    pub fn rate_limit(mut self, rate_limit: fn() -> bool) -> Self {
+        self.rate_limit = Some(rate_limit);
+        self
+    }
+
+    pub fn classify_failure(mut self, classify_failure: ClassifyFn<E>) -> Self {
+        self.classify_failure = Some(classify_failure);
+        self
+    }
+
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = Some(clock);
+        self
+    }
+
+    pub fn rng(mut self, rng: Arc<dyn Rng>) -> Self {
+        self.rng = Some(rng);
+        self
+    }
+
+    pub fn coordinate(mut self, coordination: Arc<dyn CoordinationSink>) -> Self {
+        self.coordination = Some(coordination);
+        self
+    }
+
+    pub fn share_window(mut self, window: Arc<Mutex<Window>>) -> Self {
+        self.shared_window = Some(window);
+        self
+    }
+
+    pub fn add_observer<T: Observer + Send + 'static>(mut self, observer: T) -> Self {
+        self.observers.push(Box::new(observer));
+        self
+    }
+
+    /// Construct the command. Fails with `CriusError::InvalidConfig`
+    /// if no command function was given or the configuration is
+    /// invalid.
+    pub fn build(self) -> Result<Command<I, O, E>, CriusError> {
+        let function = self.function.ok_or(CriusError::InvalidConfig)?;
+        let mut cmd = command::Command::define(self.config, function)?;
+        cmd.fallback = self.fallback;
+        cmd.failure_weight = self.failure_weight;
+        cmd.validate = self.validate;
+        cmd.intercept = self.intercept;
+        cmd.fallback_predicate = self.fallback_predicate;
+        cmd.on_decision = self.on_decision;
+        cmd.rate_limit = self.rate_limit;
+        cmd.classify_failure = self.classify_failure;
+        if let Some(clock) = self.clock {
+            cmd.clock(clock);
+        }
+        if let Some(rng) = self.rng {
+            cmd.rng(rng);
+        }
+        if let Some(coordination) = self.coordination {
+            cmd.coordinate(coordination);
+        }
+        if let Some(window) = self.shared_window {
+            cmd.share_window(window);
+        }
+        for observer in self.observers {
+            cmd.add_boxed_observer(observer);
+        }
+        Ok(cmd)
+    }
+}
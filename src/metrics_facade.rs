@@ -0,0 +1,69 @@
+//! Integration with the `metrics` crate facade, available with the
+//! `metrics` feature. Commands emitting metrics record the outcomes
+//! of their calls through whichever recorder is installed, labeled
+//! with `breaker` set to the breaker's name:
+//!
+//! * `crius.successes`: Counter of successful calls
+//! * `crius.failures`: Counter of failed calls
+//! * `crius.rejections`: Counter of calls rejected by an open breaker
+//! * `crius.state`: Gauge of the breaker state, `0` if closed and `1`
+//!   if open
+//! * `crius.latency`: Histogram of the time calls to the command
+//!   function took, in seconds
+
+use circuit_breaker::CircuitState;
+use command::Command;
+use error::CriusError;
+use instruments::{self, BREAKER_LABEL, COUNTERS, STATE};
+use metrics::{counter, gauge, histogram};
+use observer::{Observer, Outcome};
+use std::time::Duration;
+
+/// An observer recording the outcomes of a command's calls through
+/// the `metrics` facade.
+pub struct MetricsFacadeObserver {
+    name: &'static str,
+}
+
+impl MetricsFacadeObserver {
+    /// Create an observer labeling all metrics with the given breaker
+    /// name. The state gauge is only set when the observer is notified
+    /// about a state, which `Command::emit_metrics` does right away
+    /// with the breaker's current state.
+    pub fn new(name: &'static str) -> MetricsFacadeObserver {
+        MetricsFacadeObserver { name }
+    }
+}
+
+impl Observer for MetricsFacadeObserver {
+    fn on_outcome(&self, outcome: Outcome) {
+        if let Some(index) = instruments::counter_index(outcome) {
+            counter!(COUNTERS[index].name, BREAKER_LABEL => self.name).increment(1);
+        }
+    }
+
+    fn on_state_change(&self, state: CircuitState) {
+        let value = instruments::state_value(state);
+        gauge!(STATE.name, BREAKER_LABEL => self.name).set(f64::from(value));
+    }
+
+    fn on_latency(&self, latency: Duration) {
+        histogram!("crius.latency", BREAKER_LABEL => self.name).record(latency.as_secs_f64());
+    }
+}
+
+impl<I, O, E, F, FB> Command<I, O, E, F, FB>
+where
+    E: From<CriusError>,
+    F: Fn(I) -> Result<O, E>,
+    FB: Fn(E) -> O,
+{
+    /// Record the outcomes of this command's calls through the
+    /// `metrics` facade, labeled with the given breaker name. See the
+    /// `metrics_facade` module for the recorded metrics.
+    pub fn emit_metrics(&mut self, name: &'static str) -> &mut Self {
+        let observer = MetricsFacadeObserver::new(name);
+        observer.on_state_change(self.breaker().state());
+        self.add_observer(observer)
+    }
+}
@@ -0,0 +1,141 @@
+use circuit_breaker::CircuitState;
+use error::ClassifierError;
+use std::any::Any;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// An observer is notified about the outcomes of a command's calls
+/// and about changes of its breaker's state. All methods default to
+/// doing nothing, so implementors only need to override the events
+/// they are interested in.
+///
+/// Observers are only notified while the circuit breaker is enabled.
+///
+/// Calls made through `Command::run_with_context` notify the
+/// `*_with_context` methods instead, passing along the caller's
+/// context (e.g. a request id). Observers can downcast the context to
+/// the type they expect. By default these methods ignore the context
+/// and notify the methods without context.
+pub trait Observer {
+    /// Called after a call to the command function succeeded.
+    fn on_success(&self) {}
+
+    /// Called after a call to the command function failed.
+    fn on_failure(&self) {}
+
+    /// Called when a call was rejected because the breaker is open.
+    fn on_rejected(&self) {}
+
+    /// Called when the breaker opened or closed.
+    fn on_state_change(&self, _state: CircuitState) {}
+
+    /// Called with the time a call to the command function took,
+    /// before its success or failure is notified.
+    fn on_latency(&self, _latency: Duration) {}
+
+    /// Called once with the final outcome of every call, whichever
+    /// way the call ended, even while the breaker is disabled. Calls
+    /// rejected due to invalid input or by the rate limit are only
+    /// notified here.
+    fn on_outcome(&self, _outcome: Outcome) {}
+
+    /// Called when the failure classifier of a command failed or
+    /// panicked, before the call is recorded as a failure.
+    fn on_classifier_error(&self, _error: &ClassifierError) {}
+
+    /// Called instead of `on_success` for calls made with a context.
+    fn on_success_with_context(&self, _context: &dyn Any) {
+        self.on_success()
+    }
+
+    /// Called instead of `on_failure` for calls made with a context.
+    fn on_failure_with_context(&self, _context: &dyn Any) {
+        self.on_failure()
+    }
+
+    /// Called instead of `on_rejected` for calls made with a context.
+    fn on_rejected_with_context(&self, _context: &dyn Any) {
+        self.on_rejected()
+    }
+
+    /// Called instead of `on_state_change` when a call made with a
+    /// context opened or closed the breaker.
+    fn on_state_change_with_context(&self, state: CircuitState, _context: &dyn Any) {
+        self.on_state_change(state)
+    }
+
+    /// Called instead of `on_outcome` for calls made with a context.
+    fn on_outcome_with_context(&self, outcome: Outcome, _context: &dyn Any) {
+        self.on_outcome(outcome)
+    }
+}
+
+impl<T: Observer + ?Sized> Observer for Arc<T> {
+    fn on_success(&self) {
+        (**self).on_success()
+    }
+
+    fn on_failure(&self) {
+        (**self).on_failure()
+    }
+
+    fn on_rejected(&self) {
+        (**self).on_rejected()
+    }
+
+    fn on_state_change(&self, state: CircuitState) {
+        (**self).on_state_change(state)
+    }
+
+    fn on_latency(&self, latency: Duration) {
+        (**self).on_latency(latency)
+    }
+
+    fn on_outcome(&self, outcome: Outcome) {
+        (**self).on_outcome(outcome)
+    }
+
+    fn on_classifier_error(&self, error: &ClassifierError) {
+        (**self).on_classifier_error(error)
+    }
+
+    fn on_success_with_context(&self, context: &dyn Any) {
+        (**self).on_success_with_context(context)
+    }
+
+    fn on_failure_with_context(&self, context: &dyn Any) {
+        (**self).on_failure_with_context(context)
+    }
+
+    fn on_rejected_with_context(&self, context: &dyn Any) {
+        (**self).on_rejected_with_context(context)
+    }
+
+    fn on_state_change_with_context(&self, state: CircuitState, context: &dyn Any) {
+        (**self).on_state_change_with_context(state, context)
+    }
+
+    fn on_outcome_with_context(&self, outcome: Outcome, context: &dyn Any) {
+        (**self).on_outcome_with_context(outcome, context)
+    }
+}
+
+/// Final outcome of a call, see `Observer::on_outcome`.
+#[non_exhaustive]
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum Outcome {
+    /// The command function succeeded
+    Success,
+
+    /// The command function failed
+    Failure,
+
+    /// The breaker rejected the call
+    Rejected,
+
+    /// The input was rejected by the validation function
+    InvalidInput,
+
+    /// The call was rejected by the rate limit of the command
+    RateLimited,
+}
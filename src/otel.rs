@@ -0,0 +1,80 @@
+//! OpenTelemetry integration, available with the `opentelemetry`
+//! feature. Binding a meter to a command records the outcomes of its
+//! calls as OpenTelemetry instruments, with the attribute `breaker`
+//! set to the breaker's name:
+//!
+//! * `crius.successes`: Counter of successful calls
+//! * `crius.failures`: Counter of failed calls
+//! * `crius.rejections`: Counter of calls rejected by an open breaker
+//! * `crius.state`: Gauge of the breaker state, `0` if closed and `1`
+//!   if open
+
+use circuit_breaker::CircuitState;
+use command::Command;
+use error::CriusError;
+use instruments::{self, BREAKER_LABEL, COUNTERS, STATE};
+use observer::{Observer, Outcome};
+use opentelemetry::metrics::{Counter, Gauge, Meter};
+use opentelemetry::KeyValue;
+
+/// An observer recording the outcomes of a command's calls as
+/// OpenTelemetry instruments.
+pub struct MetricsObserver {
+    attributes: [KeyValue; 1],
+    counters: Vec<Counter<u64>>,
+    state: Gauge<u64>,
+}
+
+impl MetricsObserver {
+    /// Create the instruments on the given meter, recording with the
+    /// given breaker name. The state gauge is only recorded when the
+    /// observer is notified about a state, which `Command::bind_meter`
+    /// does right away with the breaker's current state.
+    pub fn new(meter: &Meter, name: &str) -> MetricsObserver {
+        MetricsObserver {
+            attributes: [KeyValue::new(BREAKER_LABEL, name.to_string())],
+            counters: COUNTERS
+                .iter()
+                .map(|counter| {
+                    meter
+                        .u64_counter(counter.name)
+                        .with_description(counter.description)
+                        .build()
+                })
+                .collect(),
+            state: meter
+                .u64_gauge(STATE.name)
+                .with_description(STATE.description)
+                .build(),
+        }
+    }
+}
+
+impl Observer for MetricsObserver {
+    fn on_outcome(&self, outcome: Outcome) {
+        if let Some(index) = instruments::counter_index(outcome) {
+            self.counters[index].add(1, &self.attributes);
+        }
+    }
+
+    fn on_state_change(&self, state: CircuitState) {
+        let value = instruments::state_value(state);
+        self.state.record(u64::from(value), &self.attributes);
+    }
+}
+
+impl<I, O, E, F, FB> Command<I, O, E, F, FB>
+where
+    E: From<CriusError>,
+    F: Fn(I) -> Result<O, E>,
+    FB: Fn(E) -> O,
+{
+    /// Record the outcomes of this command's calls on the given
+    /// meter, with the given breaker name. See the `otel` module for
+    /// the recorded instruments.
+    pub fn bind_meter(&mut self, meter: &Meter, name: &str) -> &mut Self {
+        let observer = MetricsObserver::new(meter, name);
+        observer.on_state_change(self.breaker().state());
+        self.add_observer(observer)
+    }
+}
@@ -0,0 +1,103 @@
+//! Integration with the `prometheus` crate, available with the
+//! `prometheus` feature. Registering a command's metrics adds these
+//! collectors to a registry, labeled with `breaker` set to the
+//! breaker's name:
+//!
+//! * `crius_successes_total`: Counter of successful calls
+//! * `crius_failures_total`: Counter of failed calls
+//! * `crius_rejections_total`: Counter of calls rejected by an open
+//!   breaker
+//! * `crius_state`: Gauge of the breaker state, `0` if closed and `1`
+//!   if open
+
+use circuit_breaker::CircuitState;
+use command::Command;
+use error::CriusError;
+use instruments::{self, Instrument, BREAKER_LABEL, COUNTERS, STATE};
+use observer::{Observer, Outcome};
+use prometheus::core::Collector;
+use prometheus::{IntCounter, IntGauge, Opts, Registry};
+
+/// An observer keeping Prometheus collectors up to date with the
+/// outcomes of a command's calls.
+pub struct PrometheusObserver {
+    counters: Vec<IntCounter>,
+    state: IntGauge,
+}
+
+impl PrometheusObserver {
+    /// Create the collectors for the breaker with the given name and
+    /// register them in the registry. Fails if the registry already
+    /// contains collectors for a breaker of the same name, in which
+    /// case none of the collectors stay registered. The state gauge is
+    /// only set when the observer is notified about a state, which
+    /// `Command::register_metrics` does right away with the breaker's
+    /// current state.
+    pub fn register(registry: &Registry, name: &str) -> prometheus::Result<PrometheusObserver> {
+        let opts = |instrument: &Instrument| {
+            Opts::new(instrument.prometheus_name, instrument.description)
+                .const_label(BREAKER_LABEL, name)
+        };
+
+        let observer = PrometheusObserver {
+            counters: COUNTERS
+                .iter()
+                .map(|counter| IntCounter::with_opts(opts(counter)))
+                .collect::<prometheus::Result<_>>()?,
+            state: IntGauge::with_opts(opts(&STATE))?,
+        };
+
+        for (registered, collector) in observer.collectors().into_iter().enumerate() {
+            if let Err(err) = registry.register(collector) {
+                // Unregister the collectors registered so far, so the
+                // registration can be retried:
+                for collector in observer.collectors().into_iter().take(registered) {
+                    let _ = registry.unregister(collector);
+                }
+                return Err(err);
+            }
+        }
+        Ok(observer)
+    }
+
+    fn collectors(&self) -> Vec<Box<dyn Collector>> {
+        let mut collectors: Vec<Box<dyn Collector>> = Vec::new();
+        for counter in &self.counters {
+            collectors.push(Box::new(counter.clone()));
+        }
+        collectors.push(Box::new(self.state.clone()));
+        collectors
+    }
+}
+
+impl Observer for PrometheusObserver {
+    fn on_outcome(&self, outcome: Outcome) {
+        if let Some(index) = instruments::counter_index(outcome) {
+            self.counters[index].inc();
+        }
+    }
+
+    fn on_state_change(&self, state: CircuitState) {
+        self.state.set(i64::from(instruments::state_value(state)));
+    }
+}
+
+impl<I, O, E, F, FB> Command<I, O, E, F, FB>
+where
+    E: From<CriusError>,
+    F: Fn(I) -> Result<O, E>,
+    FB: Fn(E) -> O,
+{
+    /// Register collectors for this command's calls in the given
+    /// registry, labeled with the given breaker name. See the
+    /// `prometheus_metrics` module for the registered collectors.
+    pub fn register_metrics(
+        &mut self,
+        registry: &Registry,
+        name: &str,
+    ) -> prometheus::Result<&mut Self> {
+        let observer = PrometheusObserver::register(registry, name)?;
+        observer.on_state_change(self.breaker().state());
+        Ok(self.add_observer(observer))
+    }
+}
@@ -0,0 +1,76 @@
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A source of random numbers. Breakers draw random numbers through
+/// this trait so they can be made deterministic in tests.
+pub trait Rng: Debug + Send + Sync {
+    /// Returns a number between 0.0 (inclusive) and 1.0 (exclusive).
+    fn next_f64(&self) -> f64;
+}
+
+/// Small, fast xorshift generator. Not suitable for cryptography, but
+/// good enough to spread admission decisions. This is the default
+/// random number generator.
+#[derive(Debug)]
+pub struct XorShiftRng {
+    state: AtomicU64,
+}
+
+impl XorShiftRng {
+    /// Create a generator seeded from the current system time.
+    pub fn new() -> XorShiftRng {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since_epoch| since_epoch.as_nanos() as u64)
+            .unwrap_or(0);
+        XorShiftRng::with_seed(seed)
+    }
+
+    /// Create a generator that always produces the same sequence for
+    /// the same seed.
+    pub fn with_seed(seed: u64) -> XorShiftRng {
+        // Xorshift gets stuck at zero:
+        let seed = if seed == 0 {
+            0x9e37_79b9_7f4a_7c15
+        } else {
+            seed
+        };
+        XorShiftRng {
+            state: AtomicU64::new(seed),
+        }
+    }
+
+    fn next_u64(&self) -> u64 {
+        let mut current = self.state.load(Ordering::Relaxed);
+        loop {
+            let mut next = current;
+            next ^= next << 13;
+            next ^= next >> 7;
+            next ^= next << 17;
+
+            match self.state.compare_exchange_weak(
+                current,
+                next,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return next,
+                Err(actual) => current = actual,
+            }
+        }
+    }
+}
+
+impl Default for XorShiftRng {
+    fn default() -> XorShiftRng {
+        XorShiftRng::new()
+    }
+}
+
+impl Rng for XorShiftRng {
+    fn next_f64(&self) -> f64 {
+        // Use the upper 53 bits, which is all an f64 can represent:
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
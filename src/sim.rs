@@ -0,0 +1,300 @@
+//! Simulation of a dependency and of a breaker protecting it, available
+//! with the `sim` feature. This lets users check how a configuration
+//! behaves during and after an outage without waiting for real time to
+//! pass:
+//!
+//! ```
+//! use crius::command::Config;
+//! use crius::sim::{Simulation, SimulatedDependency};
+//! use std::time::Duration;
+//!
+//! let mut dependency = SimulatedDependency::new();
+//! dependency
+//!     .phase(Duration::from_secs(10), 0.0, Duration::from_millis(20))
+//!     .phase(Duration::from_secs(20), 1.0, Duration::from_millis(20))
+//!     .phase(Duration::from_secs(30), 0.0, Duration::from_millis(20));
+//!
+//! let report = Simulation::new(dependency)
+//!     .run(Config::default(), Duration::from_secs(60))
+//!     .unwrap();
+//! assert!(report.closed_since().unwrap() < Duration::from_secs(40));
+//! ```
+//!
+//! Time is simulated with a `ManualClock`, and failures are drawn from
+//! a seeded random number generator, so simulations are reproducible.
+
+use circuit_breaker::CircuitState;
+use clock::{Clock, ManualClock};
+use command::{Command, Config};
+use error::CriusError;
+use observer::Observer;
+use rng::{Rng, XorShiftRng};
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const DEFAULT_SEED: u64 = 1;
+const DEFAULT_INTERVAL_MS: u64 = 100;
+
+/// A period of time during which a simulated dependency fails with a
+/// fixed probability and takes a fixed time to respond.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Phase {
+    pub duration: Duration,
+
+    /// Probability of a call failing, between 0.0 and 1.0
+    pub error_rate: f64,
+
+    /// Time every call takes
+    pub latency: Duration,
+}
+
+/// Error of a call to a simulated dependency.
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum SimulatedError {
+    /// The simulated dependency failed
+    Failed,
+
+    /// The breaker rejected the call
+    Rejected,
+}
+
+impl Display for SimulatedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SimulatedError::Failed => write!(f, "Simulated dependency failed"),
+            SimulatedError::Rejected => write!(f, "Call was rejected by the breaker"),
+        }
+    }
+}
+
+impl Error for SimulatedError {}
+
+impl From<CriusError> for SimulatedError {
+    fn from(_: CriusError) -> Self {
+        SimulatedError::Rejected
+    }
+}
+
+/// A dependency whose error rate and latency follow a programmed
+/// sequence of phases. Once the last phase is over, the dependency
+/// keeps behaving like in the last phase. Without any phases, calls
+/// succeed right away.
+#[derive(Debug)]
+pub struct SimulatedDependency {
+    phases: Vec<Phase>,
+    rng: Arc<dyn Rng>,
+}
+
+impl SimulatedDependency {
+    pub fn new() -> SimulatedDependency {
+        SimulatedDependency {
+            phases: Vec::new(),
+            rng: Arc::new(XorShiftRng::with_seed(DEFAULT_SEED)),
+        }
+    }
+
+    /// Append a phase of the given duration, error rate and latency.
+    pub fn phase(&mut self, duration: Duration, error_rate: f64, latency: Duration) -> &mut Self {
+        self.phases.push(Phase {
+            duration,
+            error_rate,
+            latency,
+        });
+        self
+    }
+
+    /// Draw failures from the given generator instead of the default,
+    /// seeded one.
+    pub fn rng(&mut self, rng: Arc<dyn Rng>) -> &mut Self {
+        self.rng = rng;
+        self
+    }
+
+    /// Returns the phase the dependency is in after the given time
+    /// since the start of the simulation.
+    pub fn phase_at(&self, elapsed: Duration) -> Option<Phase> {
+        let mut phase_end = Duration::from_millis(0);
+        for phase in &self.phases {
+            phase_end += phase.duration;
+            if elapsed < phase_end {
+                return Some(*phase);
+            }
+        }
+        self.phases.last().cloned()
+    }
+
+    /// Call the dependency at the given time since the start of the
+    /// simulation. Returns the outcome of the call and the time it
+    /// took.
+    pub fn call(&self, elapsed: Duration) -> (Result<(), SimulatedError>, Duration) {
+        let phase = match self.phase_at(elapsed) {
+            Some(phase) => phase,
+            None => return (Ok(()), Duration::from_millis(0)),
+        };
+
+        let result = if self.rng.next_f64() < phase.error_rate {
+            Err(SimulatedError::Failed)
+        } else {
+            Ok(())
+        };
+        (result, phase.latency)
+    }
+}
+
+impl Default for SimulatedDependency {
+    fn default() -> SimulatedDependency {
+        SimulatedDependency::new()
+    }
+}
+
+/// Outcome of a simulation. Times are relative to the start of the
+/// simulation.
+#[derive(Clone, PartialEq, Debug)]
+pub struct SimulationReport {
+    /// Times the breaker opened or closed, in chronological order
+    pub transitions: Vec<(Duration, CircuitState)>,
+
+    /// Number of calls to the dependency that succeeded
+    pub successes: u32,
+
+    /// Number of calls to the dependency that failed
+    pub failures: u32,
+
+    /// Number of calls rejected by the breaker
+    pub rejections: u32,
+}
+
+impl SimulationReport {
+    /// State of the breaker at the given time.
+    pub fn state_at(&self, at: Duration) -> CircuitState {
+        self.transitions
+            .iter()
+            .take_while(|&&(time, _)| time <= at)
+            .last()
+            .map(|&(_, state)| state)
+            .unwrap_or(CircuitState::Closed)
+    }
+
+    /// Time the breaker closed for the last time, if it opened at all
+    /// and was closed at the end of the simulation.
+    pub fn closed_since(&self) -> Option<Duration> {
+        match self.transitions.last() {
+            Some(&(time, CircuitState::Closed)) => Some(time),
+            _ => None,
+        }
+    }
+
+    /// Whether the breaker opened at some point between the given
+    /// times.
+    pub fn opened_between(&self, from: Duration, to: Duration) -> bool {
+        self.transitions
+            .iter()
+            .any(|&(time, state)| state == CircuitState::Open && time >= from && time <= to)
+    }
+}
+
+/// Runs a breaker against a simulated dependency, calling it at a
+/// fixed interval.
+#[derive(Debug)]
+pub struct Simulation {
+    dependency: Arc<SimulatedDependency>,
+    interval: Duration,
+    seed: u64,
+}
+
+impl Simulation {
+    pub fn new(dependency: SimulatedDependency) -> Simulation {
+        Simulation {
+            dependency: Arc::new(dependency),
+            interval: Duration::from_millis(DEFAULT_INTERVAL_MS),
+            seed: DEFAULT_SEED,
+        }
+    }
+
+    /// Time between the starts of consecutive calls. Calls taking
+    /// longer than the interval delay the next call.
+    pub fn interval(&mut self, interval: Duration) -> &mut Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Seed of the breaker's random number generator, e.g. for
+    /// `graduated_shedding`.
+    pub fn seed(&mut self, seed: u64) -> &mut Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Run a breaker with the given configuration against the
+    /// dependency for the given (simulated) time. Fails if the
+    /// configuration is invalid.
+    pub fn run(&self, config: Config, duration: Duration) -> Result<SimulationReport, CriusError> {
+        let clock = Arc::new(ManualClock::new());
+        let start = clock.now();
+        let recorder = Arc::new(Recorder {
+            clock: clock.clone(),
+            start,
+            report: Mutex::new(SimulationReport {
+                transitions: Vec::new(),
+                successes: 0,
+                failures: 0,
+                rejections: 0,
+            }),
+        });
+
+        let dependency = self.dependency.clone();
+        let call_clock = clock.clone();
+        let mut cmd =
+            Command::<(), (), SimulatedError, _, fn(SimulatedError)>::define(config, move |_| {
+                let elapsed = call_clock.now().saturating_duration_since(start);
+                let (result, latency) = dependency.call(elapsed);
+                call_clock.advance(latency);
+                result
+            })?;
+        cmd.clock(clock.clone())
+            .rng(Arc::new(XorShiftRng::with_seed(self.seed)))
+            .add_observer(recorder.clone());
+
+        while clock.now().saturating_duration_since(start) < duration {
+            let call_start = clock.now();
+            let _ = cmd.run(());
+
+            let took = clock.now().saturating_duration_since(call_start);
+            if took < self.interval {
+                clock.advance(self.interval - took);
+            }
+        }
+
+        let report = recorder.report.lock().unwrap().clone();
+        Ok(report)
+    }
+}
+
+/// Observer collecting the report of a simulation.
+#[derive(Debug)]
+struct Recorder {
+    clock: Arc<ManualClock>,
+    start: Instant,
+    report: Mutex<SimulationReport>,
+}
+
+impl Observer for Recorder {
+    fn on_success(&self) {
+        self.report.lock().unwrap().successes += 1;
+    }
+
+    fn on_failure(&self) {
+        self.report.lock().unwrap().failures += 1;
+    }
+
+    fn on_rejected(&self) {
+        self.report.lock().unwrap().rejections += 1;
+    }
+
+    fn on_state_change(&self, state: CircuitState) {
+        let time = self.clock.now().saturating_duration_since(self.start);
+        self.report.lock().unwrap().transitions.push((time, state));
+    }
+}
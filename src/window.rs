@@ -1,7 +1,15 @@
+use clock::{Clock, SystemClock};
 use std::collections::vec_deque::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 use command::Config;
 
+/// Source of the sequence numbers of points, shared by all windows so
+/// that the points of different windows can be ordered, too.
+static SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+#[allow(clippy::upper_case_acronyms)]
 #[derive(PartialEq, Eq, Hash, Copy, Clone, Debug)]
 pub enum Point {
     SUCCESS,
@@ -11,22 +19,34 @@ pub enum Point {
 #[derive(Clone, Debug)]
 struct Bucket {
     points: Vec<Point>,
+
+    /// Sequence numbers of the most recent points, in the same order
+    /// as `points`, if the window records sequences
+    sequences: Vec<u64>,
+
+    /// Sum of the weights of all failures recorded in this bucket
+    failure_score: f64,
+
     timestamp: Instant,
 }
 
 impl Bucket {
-    fn new() -> Bucket {
+    fn starting_at(timestamp: Instant) -> Bucket {
         Bucket {
             points: Vec::new(),
-            timestamp: Instant::now(),
+            sequences: Vec::new(),
+            failure_score: 0.0,
+            timestamp,
         }
     }
 }
 
 #[derive(Clone, Debug)]
 pub struct Window {
-    /// Queue structure holding the window's buckets
-    buckets: VecDeque<Bucket>,
+    /// Queue structure holding the window's buckets. Buckets are
+    /// shared with clones and snapshots of the window and copied on
+    /// write.
+    buckets: VecDeque<Arc<Bucket>>,
 
     /// Period during which a single bucket is valid
     bucket_ms: Duration,
@@ -36,12 +56,24 @@ pub struct Window {
 
     /// Total size of all buckets in a window
     window_size: Duration,
+
+    /// Source of the current time
+    clock: Arc<dyn Clock>,
+
+    /// Whether points are tagged with sequence numbers, see
+    /// `record_sequences`
+    sequenced: bool,
 }
 
 impl Window {
     /// Attempt to create a window from a given configuration. Returns
     /// `None` if the duration calculations overflowed.
     pub fn new(config: Config) -> Option<Self> {
+        Window::with_clock(config, Arc::new(SystemClock))
+    }
+
+    /// Like `new`, but reads the current time from the given clock.
+    pub fn with_clock(config: Config, clock: Arc<dyn Clock>) -> Option<Self> {
         let bucket_ms = Duration::from_millis(config.bucket_size_in_ms);
         let window_size = bucket_ms.checked_mul(config.buckets_in_window);
 
@@ -50,32 +82,273 @@ impl Window {
             window_size,
             buckets: VecDeque::new(),
             buckets_nr: config.buckets_in_window,
+            clock,
+            sequenced: false,
         })
     }
 
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    /// Tag every point added from now on with a sequence number, see
+    /// `bucket_summaries`. Tagging costs an atomic increment and a
+    /// `u64` per point, so it is off by default.
+    pub fn record_sequences(&mut self) {
+        self.sequenced = true;
+    }
+
     pub fn add_point(&mut self, point: Point) {
+        self.add_weighted_point(point, 1.0)
+    }
+
+    /// Adds a point to the window. The weight only applies to
+    /// failures and is accumulated into the window's failure score.
+    pub fn add_weighted_point(&mut self, point: Point, weight: f64) {
+        let sequenced = self.sequenced;
         let current_bucket = self.update_window_returning_latest_bucket();
         current_bucket.points.push(point);
+        if sequenced {
+            current_bucket
+                .sequences
+                .push(SEQUENCE.fetch_add(1, Ordering::Relaxed));
+        }
+        if point == Point::FAILURE {
+            current_bucket.failure_score += weight;
+        }
     }
 
     pub fn clear_window(&mut self) {
         self.buckets.clear();
     }
 
+    /// Drops expired buckets and returns whether no points remain in
+    /// the window:
+    pub fn is_empty(&mut self) -> bool {
+        self.prune_expired_buckets();
+        self.valid_buckets().all(|bucket| bucket.points.is_empty())
+    }
+
     /// Returns the points of all currently valid buckets:
     pub fn get_points(&mut self) -> Vec<Point> {
-        let threshold = Instant::now() - self.window_size;
-        self.buckets
+        self.valid_buckets().fold(vec![], |mut acc, bucket| {
+            acc.extend(&bucket.points);
+            acc
+        })
+    }
+
+    /// Returns the points of the given number of most recent valid
+    /// buckets:
+    pub fn get_recent_points(&mut self, buckets: usize) -> Vec<Point> {
+        let mut recent: Vec<&Arc<Bucket>> = self.valid_buckets().rev().take(buckets).collect();
+        recent.reverse();
+        recent.into_iter().fold(vec![], |mut acc, bucket| {
+            acc.extend(&bucket.points);
+            acc
+        })
+    }
+
+    /// Returns the summed failure weights of all currently valid
+    /// buckets:
+    pub fn get_failure_score(&mut self) -> f64 {
+        self.valid_buckets()
+            .map(|bucket| bucket.failure_score)
+            .sum()
+    }
+
+    /// Returns the summed failure weights of the given number of most
+    /// recent valid buckets:
+    pub fn get_recent_failure_score(&mut self, buckets: u32) -> f64 {
+        self.valid_buckets()
+            .rev()
+            .take(buckets as usize)
+            .map(|bucket| bucket.failure_score)
+            .sum()
+    }
+
+    /// Returns a read-only view of the currently valid buckets. The
+    /// snapshot shares the buckets with the window instead of copying
+    /// their points. Should the window add points to a shared bucket
+    /// later on, only that bucket is copied.
+    pub fn snapshot(&self) -> WindowSnapshot {
+        WindowSnapshot {
+            buckets: self.valid_buckets().cloned().collect(),
+        }
+    }
+
+    /// Returns how much history the currently valid buckets cover.
+    /// Empty buckets are ignored.
+    pub fn coverage(&self) -> WindowCoverage {
+        let oldest = self
+            .valid_buckets()
+            .find(|bucket| !bucket.points.is_empty())
+            .map(|bucket| bucket.timestamp);
+        let span = match oldest {
+            Some(oldest) => self.clock.now().saturating_duration_since(oldest),
+            None => Duration::from_millis(0),
+        };
+
+        WindowCoverage {
+            oldest,
+            total_points: self.valid_buckets().map(|bucket| bucket.points.len()).sum(),
+            span,
+        }
+    }
+
+    /// Returns the start and the points of every currently valid
+    /// bucket, with the sequence number each point was recorded with.
+    /// Sequence numbers increase with every point added to any window,
+    /// so they tell the order in which points were recorded, e.g. by
+    /// different threads.
+    ///
+    /// Returns `None` unless the window records sequences, see
+    /// `record_sequences`. Points added before are left out.
+    pub fn bucket_summaries(&self) -> Option<Vec<BucketSummary>> {
+        if !self.sequenced {
+            return None;
+        }
+
+        let summaries = self
+            .valid_buckets()
+            .map(|bucket| {
+                let untagged = bucket.points.len() - bucket.sequences.len();
+                BucketSummary {
+                    timestamp: bucket.timestamp,
+                    points: bucket
+                        .sequences
+                        .iter()
+                        .cloned()
+                        .zip(bucket.points[untagged..].iter().cloned())
+                        .collect(),
+                }
+            })
+            .collect();
+        Some(summaries)
+    }
+
+    /// Exports the currently valid buckets along with their points.
+    /// Unlike `snapshot`, the export owns its points and records the
+    /// start of every bucket relative to the current time, so it can
+    /// be restored into a window with a different clock, see
+    /// `from_export`.
+    pub fn export(&self) -> ExportedWindow {
+        let now = self.clock.now();
+        ExportedWindow {
+            buckets: self
+                .valid_buckets()
+                .map(|bucket| ExportedBucket {
+                    age: now.duration_since(bucket.timestamp),
+                    points: bucket.points.clone(),
+                    failure_score: bucket.failure_score,
+                })
+                .collect(),
+        }
+    }
+
+    /// Like `with_clock`, but starts out with the buckets of the given
+    /// export, see `import`.
+    pub fn from_export(
+        config: Config,
+        clock: Arc<dyn Clock>,
+        export: &ExportedWindow,
+    ) -> Option<Self> {
+        Window::with_clock(config, clock).map(|mut window| {
+            window.import(export);
+            window
+        })
+    }
+
+    /// Replaces the buckets of the window with the ones of the given
+    /// export. Bucket starts are restored relative to the current
+    /// time. Buckets that would start before the clock's earliest
+    /// representable time are dropped, as are the oldest buckets if
+    /// the export holds more than fit into the window. Restored points
+    /// get new sequence numbers if the window records sequences.
+    pub fn import(&mut self, export: &ExportedWindow) {
+        let now = self.clock.now();
+        let sequenced = self.sequenced;
+        self.buckets = export
+            .buckets
             .iter()
-            .filter(|bucket| bucket.timestamp > threshold)
-            .fold(vec![], |mut acc, bucket| {
-                acc.extend(&bucket.points);
-                return acc;
+            .filter_map(|bucket| {
+                now.checked_sub(bucket.age).map(|timestamp| {
+                    Arc::new(Bucket {
+                        points: bucket.points.clone(),
+                        sequences: bucket
+                            .points
+                            .iter()
+                            .filter(|_| sequenced)
+                            .map(|_| SEQUENCE.fetch_add(1, Ordering::Relaxed))
+                            .collect(),
+                        failure_score: bucket.failure_score,
+                        timestamp,
+                    })
+                })
             })
+            .collect();
+        while self.buckets.len() > self.buckets_nr as usize {
+            self.buckets.pop_front();
+        }
+    }
+
+    /// Returns the buckets that have neither expired nor start after
+    /// the current time. The latter can only happen if the clock went
+    /// backwards, in which case those buckets are ignored.
+    fn valid_buckets<'a>(&'a self) -> impl DoubleEndedIterator<Item = &'a Arc<Bucket>> + 'a {
+        let now = self.clock.now();
+        let threshold = now.checked_sub(self.window_size);
+        self.buckets.iter().filter(move |bucket| {
+            let expired = match threshold {
+                Some(threshold) => bucket.timestamp <= threshold,
+                None => false,
+            };
+            !expired && bucket.timestamp <= now
+        })
+    }
+
+    fn prune_expired_buckets(&mut self) {
+        let threshold = match self.clock.now().checked_sub(self.window_size) {
+            Some(threshold) => threshold,
+            None => return,
+        };
+
+        while let Some(bucket) = self.buckets.front() {
+            if bucket.timestamp > threshold {
+                break;
+            }
+            self.buckets.pop_front();
+        }
+    }
+
+    /// Returns the start of the bucket period containing `now`, for
+    /// periods following each other from `first_start` on. Without
+    /// this, the first points after a pause would each start a new
+    /// bucket until the buckets caught up with the current time.
+    fn period_start(&self, first_start: Instant, now: Instant) -> Instant {
+        let bucket_nanos = self.bucket_ms.as_nanos();
+        if bucket_nanos == 0 {
+            return now;
+        }
+
+        let offset = now.duration_since(first_start).as_nanos() % bucket_nanos;
+        now - Duration::new(
+            (offset / 1_000_000_000) as u64,
+            (offset % 1_000_000_000) as u32,
+        )
     }
 
     fn update_window_returning_latest_bucket(&mut self) -> &mut Bucket {
-        let now = Instant::now();
+        let now = self.clock.now();
+
+        // Drop buckets starting after the current time, which only
+        // exist if the clock went backwards:
+        while let Some(bucket) = self.buckets.back() {
+            if bucket.timestamp <= now {
+                break;
+            }
+            self.buckets.pop_back();
+        }
+
         let latest_threshold = self.buckets
             .back()
             .map(|bucket| bucket.timestamp + self.bucket_ms);
@@ -83,25 +356,130 @@ impl Window {
         if let Some(threshold) = latest_threshold {
             // Return the latest bucket if it is still current:
             if threshold > now {
-                return self.buckets.back_mut().unwrap();
+                return Arc::make_mut(self.buckets.back_mut().unwrap());
             }
 
-            // Otherwise create and return a new bucket:
-            let new_bucket = Bucket {
-                points: Vec::new(),
-                timestamp: threshold,
-            };
+            // Otherwise create and return a new bucket for the bucket
+            // period the current time falls into:
+            let new_bucket = Bucket::starting_at(self.period_start(threshold, now));
 
-            self.buckets.push_back(new_bucket);
+            self.buckets.push_back(Arc::new(new_bucket));
             if self.buckets.len() > self.buckets_nr as usize {
                 self.buckets.pop_front();
             }
-            return self.buckets.back_mut().unwrap();
+            Arc::make_mut(self.buckets.back_mut().unwrap())
         } else {
             // Create a bucket if there aren't any in the window currently:
-            let first_bucket = Bucket::new();
-            self.buckets.push_back(first_bucket);
-            return self.buckets.back_mut().unwrap();
+            let first_bucket = Bucket::starting_at(now);
+            self.buckets.push_back(Arc::new(first_bucket));
+            Arc::make_mut(self.buckets.back_mut().unwrap())
         }
     }
 }
+
+/// History backing the statistics of a window, see
+/// `Window::coverage`. An error rate is the more meaningful, the more
+/// points and the longer the span it is based on.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct WindowCoverage {
+    /// Start of the oldest valid bucket holding any points
+    pub oldest: Option<Instant>,
+
+    /// Number of points in all valid buckets
+    pub total_points: usize,
+
+    /// Time from the start of the oldest bucket until now, zero for an
+    /// empty window
+    pub span: Duration,
+}
+
+/// Summary of a bucket, see `Window::bucket_summaries`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct BucketSummary {
+    pub timestamp: Instant,
+
+    /// Sequence numbers and points, in the order they were recorded
+    pub points: Vec<(u64, Point)>,
+}
+
+/// Owned export of the buckets of a window, see `Window::export`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct ExportedWindow {
+    /// Buckets from oldest to newest
+    pub buckets: Vec<ExportedBucket>,
+}
+
+/// Bucket of an `ExportedWindow`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct ExportedBucket {
+    /// Time between the start of the bucket and the export
+    pub age: Duration,
+
+    pub points: Vec<Point>,
+
+    /// Sum of the weights of all failures recorded in this bucket
+    pub failure_score: f64,
+}
+
+/// Read-only view of the buckets that were valid when the snapshot of
+/// a window was taken, see `Window::snapshot`.
+#[derive(Clone, Debug)]
+pub struct WindowSnapshot {
+    buckets: Vec<Arc<Bucket>>,
+}
+
+impl WindowSnapshot {
+    pub fn points<'a>(&'a self) -> impl Iterator<Item = Point> + 'a {
+        self.buckets
+            .iter()
+            .flat_map(|bucket| bucket.points.iter().cloned())
+    }
+
+    pub fn success_nr(&self) -> usize {
+        self.points()
+            .filter(|&point| point == Point::SUCCESS)
+            .count()
+    }
+
+    pub fn error_nr(&self) -> usize {
+        self.points()
+            .filter(|&point| point == Point::FAILURE)
+            .count()
+    }
+
+    pub fn failure_score(&self) -> f64 {
+        self.buckets.iter().map(|bucket| bucket.failure_score).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clock::ManualClock;
+
+    /// Whether all buckets of the snapshot are still shared with the
+    /// window, i.e. none of their points were copied.
+    fn shares_storage(snapshot: &WindowSnapshot, window: &Window) -> bool {
+        snapshot.buckets.iter().all(|bucket| {
+            window
+                .buckets
+                .iter()
+                .any(|window_bucket| Arc::ptr_eq(bucket, window_bucket))
+        })
+    }
+
+    #[test]
+    fn copies_shared_buckets_on_write() {
+        let clock = Arc::new(ManualClock::new());
+        let mut window = Window::with_clock(Config::default(), clock.clone()).unwrap();
+        window.add_point(Point::SUCCESS);
+        clock.advance(Duration::from_millis(1000));
+        window.add_point(Point::FAILURE);
+
+        let snapshot = window.snapshot();
+        assert!(shares_storage(&snapshot, &window));
+
+        window.add_point(Point::FAILURE);
+        assert!(!shares_storage(&snapshot, &window));
+    }
+}
@@ -0,0 +1,116 @@
+extern crate crius;
+
+mod atomic_window {
+    use crius::atomic_window::AtomicWindow;
+    use crius::clock::ManualClock;
+    use crius::command::Config;
+    use crius::window::Point;
+    use std::sync::Arc;
+    use std::{thread, time};
+
+    #[test]
+    fn counts_points() {
+        let window = AtomicWindow::new(Config::default()).unwrap();
+        window.add_point(Point::SUCCESS);
+        window.add_point(Point::FAILURE);
+        window.add_point(Point::FAILURE);
+
+        assert_eq!(1, window.success_nr());
+        assert_eq!(2, window.error_nr());
+    }
+
+    #[test]
+    fn clears_points() {
+        let window = AtomicWindow::new(Config::default()).unwrap();
+        window.add_point(Point::SUCCESS);
+        window.add_point(Point::FAILURE);
+        window.clear_window();
+
+        assert_eq!(0, window.success_nr());
+        assert_eq!(0, window.error_nr());
+    }
+
+    #[test]
+    fn expires_points() {
+        let clock = Arc::new(ManualClock::new());
+        let config = *Config::default().buckets_in_window(2).bucket_size_in_ms(10);
+        let window = AtomicWindow::with_clock(config, clock.clone()).unwrap();
+        window.add_point(Point::FAILURE);
+        assert_eq!(1, window.error_nr());
+
+        clock.advance(time::Duration::from_millis(30));
+        assert_eq!(0, window.error_nr());
+
+        window.add_point(Point::FAILURE);
+        assert_eq!(1, window.error_nr());
+    }
+
+    #[test]
+    fn rejects_empty_windows() {
+        assert!(AtomicWindow::new(*Config::default().buckets_in_window(0)).is_none());
+        assert!(AtomicWindow::new(*Config::default().bucket_size_in_ms(0)).is_none());
+    }
+
+    #[test]
+    fn counts_consistently_across_threads() {
+        let config = *Config::default()
+            .buckets_in_window(10)
+            .bucket_size_in_ms(60_000);
+        let window = Arc::new(AtomicWindow::new(config).unwrap());
+
+        let threads: Vec<_> = (0..16)
+            .map(|i| {
+                let window = window.clone();
+                thread::spawn(move || {
+                    for _ in 0..10_000 {
+                        window.add_point(if i % 2 == 0 {
+                            Point::SUCCESS
+                        } else {
+                            Point::FAILURE
+                        });
+                        window.success_nr();
+                    }
+                })
+            })
+            .collect();
+
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        assert_eq!(80_000, window.success_nr());
+        assert_eq!(80_000, window.error_nr());
+    }
+
+    #[test]
+    fn counts_consistently_across_threads_while_rotating() {
+        let config = *Config::default()
+            .buckets_in_window(1000)
+            .bucket_size_in_ms(1);
+        let clock = Arc::new(ManualClock::new());
+        let window = Arc::new(AtomicWindow::with_clock(config, clock.clone()).unwrap());
+
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                let window = window.clone();
+                let clock = clock.clone();
+                thread::spawn(move || {
+                    for i in 0..10_000 {
+                        if i % 100 == 0 {
+                            clock.advance(time::Duration::from_millis(1));
+                        }
+                        window.add_point(Point::FAILURE);
+                    }
+                })
+            })
+            .collect();
+
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        // The clock advanced by 800ms, so every point lands in a
+        // bucket that is still valid in the window of a full second:
+        assert_eq!(80_000, window.error_nr());
+    }
+}
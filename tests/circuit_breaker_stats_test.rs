@@ -0,0 +1,94 @@
+extern crate crius;
+
+mod circuit_breaker_stats {
+    use crius::circuit_breaker_stats::CircuitBreakerStats;
+    use crius::clock::ManualClock;
+    use crius::command::Config;
+    use crius::window::{Point, Window};
+    use std::sync::Arc;
+    use std::{thread, time};
+
+    fn stats(config: Config) -> CircuitBreakerStats {
+        CircuitBreakerStats {
+            window: Window::new(config).unwrap(),
+        }
+    }
+
+    fn assert_empty(stats: &mut CircuitBreakerStats) {
+        assert!(stats.window.is_empty());
+        assert_eq!(0, stats.success_nr());
+        assert_eq!(0, stats.error_nr());
+        assert_eq!(0, stats.success_percentage());
+        assert_eq!(0, stats.error_percentage());
+        assert_eq!(0.0, stats.error_rate());
+        assert_eq!(0.0, stats.error_score());
+    }
+
+    #[test]
+    fn new_window_is_empty() {
+        let mut stats = stats(Config::default());
+        assert_empty(&mut stats);
+    }
+
+    #[test]
+    fn cleared_window_is_empty() {
+        let mut stats = stats(Config::default());
+        stats.add_point(Point::SUCCESS);
+        stats.add_point(Point::FAILURE);
+        assert!(!stats.window.is_empty());
+
+        stats.clear();
+        assert_empty(&mut stats);
+    }
+
+    #[test]
+    fn expired_window_is_empty() {
+        let mut stats = stats(*Config::default().buckets_in_window(2).bucket_size_in_ms(10));
+        stats.add_point(Point::FAILURE);
+        assert!(!stats.window.is_empty());
+
+        thread::sleep(time::Duration::from_millis(30));
+        assert_empty(&mut stats);
+    }
+
+    #[test]
+    fn computes_percentages_of_mixed_points() {
+        let mut stats = stats(Config::default());
+        stats.add_point(Point::SUCCESS);
+        stats.add_point(Point::SUCCESS);
+        stats.add_point(Point::SUCCESS);
+        stats.add_point(Point::FAILURE);
+
+        assert_eq!(75, stats.success_percentage());
+        assert_eq!(25, stats.error_percentage());
+        assert_eq!(0.25, stats.error_rate());
+    }
+
+    #[test]
+    fn computes_error_rate_of_last_buckets() {
+        let clock = Arc::new(ManualClock::new());
+        let config = *Config::default()
+            .buckets_in_window(10)
+            .bucket_size_in_ms(1000);
+        let mut stats = CircuitBreakerStats {
+            window: Window::with_clock(config, clock.clone()).unwrap(),
+        };
+
+        // One bucket of successes, followed by buckets with an error
+        // rate of 50% and 100%:
+        stats.add_point(Point::SUCCESS);
+        stats.add_point(Point::SUCCESS);
+        clock.advance(time::Duration::from_secs(1));
+        stats.add_point(Point::SUCCESS);
+        stats.add_point(Point::FAILURE);
+        clock.advance(time::Duration::from_secs(1));
+        stats.add_point(Point::FAILURE);
+        stats.add_point(Point::FAILURE);
+
+        assert_eq!(1.0, stats.error_rate_last_buckets(1));
+        assert_eq!(0.75, stats.error_rate_last_buckets(2));
+        assert_eq!(0.5, stats.error_rate_last_buckets(3));
+        assert_eq!(0.5, stats.error_rate_last_buckets(10));
+        assert_eq!(0.0, stats.error_rate_last_buckets(0));
+    }
+}
@@ -1,12 +1,33 @@
+#![allow(clippy::needless_return, clippy::redundant_closure)]
+
 extern crate crius;
 
 mod circuit_breaker {
     use crius::command::Config;
+    use crius::command::Classification;
+    use crius::command::BreakerMode;
+    use crius::command::{Decision, RejectionReason};
+    use crius::command::{Degradable, DegradationReason};
+    use crius::command::LatencyGuard;
+    use crius::command::OperationClass;
     use crius::command::Command;
-    use crius::error::CriusError;
+    use crius::coordination::CoordinationSink;
+    use crius::circuit_breaker::Metrics;
+    use crius::circuit_breaker::CircuitState;
+    use crius::circuit_breaker::CircuitBreaker;
+    use crius::clock::ManualClock;
+    use crius::observer::{Observer, Outcome};
+    use crius::error::{ClassifierError, CriusError};
+    use crius::rng::{Rng, XorShiftRng};
+    use crius::window::{Point, Window};
+    use std::cell::{Cell, RefCell};
+    use std::rc::Rc;
+    use std::any::Any;
     use std::error::Error;
     use std::fmt::Display;
     use std::fmt;
+    use std::ops::ControlFlow;
+    use std::sync::{Arc, Mutex};
     use std::{thread, time};
 
     #[derive(PartialEq, Eq, Copy, Clone, Debug)]
@@ -123,4 +144,1681 @@ mod circuit_breaker {
             assert_eq!(5, result.unwrap());
         }
     }
+
+    #[test]
+    fn heavily_weighted_failures_trip_sooner() {
+        let config = *Config::default().error_threshold(10);
+
+        let mut heavy =
+            TestCommand::<(), ()>::define(config, |_| Err(TestError::Internal)).unwrap();
+        heavy.failure_weight(|_| 5.0);
+
+        let mut light =
+            TestCommand::<(), ()>::define(config, |_| Err(TestError::Internal)).unwrap();
+        light.failure_weight(|_| 0.5);
+
+        for _ in 0..2 {
+            assert_eq!(TestError::Internal, heavy.run(()).unwrap_err());
+            assert_eq!(TestError::Internal, light.run(()).unwrap_err());
+        }
+
+        // Two failures weighing 5.0 reach the threshold of 10:
+        assert_eq!(TestError::External, heavy.run(()).unwrap_err());
+
+        // Two failures weighing 0.5 do not:
+        assert_eq!(TestError::Internal, light.run(()).unwrap_err());
+    }
+
+    #[test]
+    fn failure_weight_defaults_to_one() {
+        let config = *Config::default().error_threshold(3);
+        let mut cmd = TestCommand::<(), ()>::define(config, |_| Err(TestError::Internal)).unwrap();
+
+        for _ in 0..3 {
+            assert_eq!(TestError::Internal, cmd.run(()).unwrap_err());
+        }
+
+        assert_eq!(TestError::External, cmd.run(()).unwrap_err());
+    }
+
+    #[test]
+    fn repeated_identical_errors_trip_sooner() {
+        fn fail_with(internal: bool) -> Result<(), TestError> {
+            if internal {
+                Err(TestError::Internal)
+            } else {
+                Err(TestError::External)
+            }
+        }
+
+        let config = *Config::default().error_threshold(5);
+
+        let mut repeated = TestCommand::<bool, ()>::define(config, fail_with).unwrap();
+        repeated.escalate_repeated_errors(2.0);
+
+        let mut mixed = TestCommand::<bool, ()>::define(config, fail_with).unwrap();
+        mixed.escalate_repeated_errors(2.0);
+
+        for &internal in &[true, false, true] {
+            assert_eq!(TestError::Internal, repeated.run(true).unwrap_err());
+            mixed.run(internal).unwrap_err();
+        }
+
+        // Identical failures weigh 1.0, 2.0 and 4.0, reaching the
+        // threshold of 5:
+        assert_eq!(TestError::External, repeated.run(true).unwrap_err());
+
+        // Distinct failures weigh 1.0 each and do not:
+        assert_eq!(TestError::Internal, mixed.run(true).unwrap_err());
+    }
+
+    thread_local! {
+        static CALLS: Cell<u32> = const { Cell::new(0) };
+    }
+
+    fn fails_twice_then_succeeds(_: ()) -> Result<u8, TestError> {
+        CALLS.with(|calls| {
+            calls.set(calls.get() + 1);
+            if calls.get() <= 2 {
+                Err(TestError::Internal)
+            } else {
+                Ok(5)
+            }
+        })
+    }
+
+    #[test]
+    fn traces_retried_attempts() {
+        let config = *Config::default()
+            .max_retries(2)
+            .error_threshold(1)
+            .error_threshold_percentage(1);
+        let mut cmd = TestCommand::<(), u8>::define(config, fails_twice_then_succeeds).unwrap();
+
+        let (result, attempts) = cmd.run_traced(());
+        assert_eq!(5, result.unwrap());
+
+        let attempt_nrs: Vec<_> = attempts.iter().map(|record| record.attempt).collect();
+        let outcomes: Vec<_> = attempts.iter().map(|record| record.outcome).collect();
+        assert_eq!(vec![1, 2, 3], attempt_nrs);
+        assert_eq!(
+            vec![Point::FAILURE, Point::FAILURE, Point::SUCCESS],
+            outcomes
+        );
+
+        // Only the final SUCCESS was recorded, otherwise the two
+        // failures would have opened the breaker:
+        assert_eq!(5, cmd.run(()).unwrap());
+    }
+
+    #[test]
+    fn stops_retrying_after_max_retries() {
+        let config = *Config::default().max_retries(1);
+        let mut cmd = TestCommand::<(), u8>::define(config, fails_twice_then_succeeds).unwrap();
+
+        let (result, attempts) = cmd.run_traced(());
+        assert_eq!(TestError::Internal, result.unwrap_err());
+        assert_eq!(2, attempts.len());
+    }
+
+    #[test]
+    fn traces_no_attempts_if_circuit_open() {
+        let config = *Config::default().error_threshold(1).max_retries(2);
+        let mut cmd = TestCommand::<(), ()>::define(config, |_| Err(TestError::Internal)).unwrap();
+
+        let (_, attempts) = cmd.run_traced(());
+        assert_eq!(3, attempts.len());
+
+        let (result, attempts) = cmd.run_traced(());
+        assert_eq!(TestError::External, result.unwrap_err());
+        assert!(attempts.is_empty());
+    }
+
+    #[derive(Default)]
+    struct RecordingObserver {
+        events: Mutex<Vec<String>>,
+    }
+
+    impl RecordingObserver {
+        fn record(&self, event: &str) {
+            self.events.lock().unwrap().push(event.to_string());
+        }
+    }
+
+    impl Observer for RecordingObserver {
+        fn on_success(&self) {
+            self.record("success");
+        }
+
+        fn on_failure(&self) {
+            self.record("failure");
+        }
+
+        fn on_rejected(&self) {
+            self.record("rejected");
+        }
+
+        fn on_state_change(&self, state: CircuitState) {
+            self.record(&format!("{:?}", state));
+        }
+    }
+
+    #[test]
+    fn notifies_observers() {
+        let observer = Arc::new(RecordingObserver::default());
+        let mut cmd =
+            TestCommand::<bool, ()>::define(*Config::default().error_threshold(1), |ok| {
+                if ok {
+                    Ok(())
+                } else {
+                    Err(TestError::Internal)
+                }
+            })
+            .unwrap();
+        cmd.add_observer(observer.clone());
+
+        cmd.run(true).unwrap();
+        cmd.run(false).unwrap_err();
+        cmd.run(true).unwrap_err();
+
+        assert_eq!(
+            vec!["success", "failure", "Open", "rejected"],
+            *observer.events.lock().unwrap()
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_input_without_running_command() {
+        let config = *Config::default().error_threshold(1);
+        let mut cmd = TestCommand::<u8, u8>::define(config, |_| Err(TestError::Internal)).unwrap();
+        cmd.validate(|&n| n < 10);
+
+        for _ in 0..5 {
+            assert_eq!(TestError::External, cmd.run(10).unwrap_err());
+        }
+
+        // The rejected inputs neither ran the command nor opened the
+        // breaker:
+        assert_eq!(TestError::Internal, cmd.run(5).unwrap_err());
+    }
+
+    #[test]
+    fn runs_command_with_valid_input() {
+        let mut cmd = TestCommand::<u8, u8>::define(Config::default(), Ok).unwrap();
+        cmd.validate(|&n| n < 10);
+
+        assert_eq!(5, cmd.run(5).unwrap());
+        assert_eq!(TestError::External, cmd.run(10).unwrap_err());
+    }
+
+    #[test]
+    fn stays_open_if_clock_goes_backwards() {
+        let clock = Arc::new(ManualClock::new());
+        let config = *Config::default().error_threshold(1).circuit_open_ms(1000);
+        let mut cmd = TestCommand::<(), ()>::define(config, |_| Err(TestError::Internal)).unwrap();
+        cmd.clock(clock.clone());
+
+        assert_eq!(TestError::Internal, cmd.run(()).unwrap_err());
+        assert_eq!(TestError::External, cmd.run(()).unwrap_err());
+
+        clock.rewind(time::Duration::from_secs(60));
+        assert_eq!(TestError::External, cmd.run(()).unwrap_err());
+
+        // Once the clock passes the open time again, the breaker
+        // closes as usual:
+        clock.advance(time::Duration::from_secs(61));
+        assert_eq!(TestError::Internal, cmd.run(()).unwrap_err());
+    }
+
+    #[test]
+    fn distinguishes_raw_and_user_visible_outcomes() {
+        let mut cmd = Command::define_with_fallback(
+            Config::default(),
+            |ok| if ok { Ok(1) } else { Err(TestError::Internal) },
+            |_| 2,
+        )
+        .unwrap();
+
+        cmd.run(true).unwrap();
+        cmd.run(false).unwrap();
+        cmd.run(false).unwrap();
+        cmd.run(false).unwrap();
+
+        assert_eq!(0.75, cmd.error_rate());
+        assert_eq!(1.0, cmd.user_visible_success_rate());
+    }
+
+    #[test]
+    fn counts_rejections_as_user_visible_failures() {
+        let config = *Config::default().error_threshold(1);
+        let mut cmd = TestCommand::<(), ()>::define(config, |_| Err(TestError::Internal)).unwrap();
+
+        cmd.run(()).unwrap_err();
+        cmd.run(()).unwrap_err();
+
+        // Opening the breaker cleared the raw window:
+        assert_eq!(0.0, cmd.error_rate());
+        assert_eq!(0.0, cmd.user_visible_success_rate());
+    }
+
+    #[derive(Debug, Default)]
+    struct MockCoordinator {
+        decision: Mutex<Option<bool>>,
+        reports: Mutex<Vec<Metrics>>,
+    }
+
+    impl CoordinationSink for MockCoordinator {
+        fn report(&self, metrics: Metrics) {
+            self.reports.lock().unwrap().push(metrics);
+        }
+
+        fn fleet_should_open(&self) -> Option<bool> {
+            *self.decision.lock().unwrap()
+        }
+    }
+
+    #[test]
+    fn coordinator_forces_circuit_open() {
+        let coordinator = Arc::new(MockCoordinator::default());
+        *coordinator.decision.lock().unwrap() = Some(true);
+
+        let mut cmd = TestCommand::<(), u8>::define(Config::default(), |_| Ok(5)).unwrap();
+        cmd.coordinate(coordinator.clone());
+
+        assert_eq!(TestError::External, cmd.run(()).unwrap_err());
+        assert_eq!(CircuitState::Open, cmd.metrics().state);
+
+        *coordinator.decision.lock().unwrap() = None;
+        assert_eq!(5, cmd.run(()).unwrap());
+        assert_eq!(CircuitState::Closed, cmd.metrics().state);
+    }
+
+    #[test]
+    fn coordinator_forces_circuit_closed() {
+        let coordinator = Arc::new(MockCoordinator::default());
+        let config = *Config::default().error_threshold(1);
+        let mut cmd = TestCommand::<(), ()>::define(config, |_| Err(TestError::Internal)).unwrap();
+        cmd.coordinate(coordinator.clone());
+
+        assert_eq!(TestError::Internal, cmd.run(()).unwrap_err());
+        assert_eq!(TestError::External, cmd.run(()).unwrap_err());
+
+        *coordinator.decision.lock().unwrap() = Some(false);
+        assert_eq!(TestError::Internal, cmd.run(()).unwrap_err());
+        assert_eq!(CircuitState::Closed, cmd.metrics().state);
+    }
+
+    #[test]
+    fn reports_local_metrics_to_coordinator() {
+        let coordinator = Arc::new(MockCoordinator::default());
+        let mut cmd =
+            TestCommand::<(), ()>::define(Config::default(), |_| Err(TestError::Internal)).unwrap();
+        cmd.coordinate(coordinator.clone());
+
+        cmd.run(()).unwrap_err();
+        cmd.run(()).unwrap_err();
+
+        let reports = coordinator.reports.lock().unwrap();
+        assert_eq!(2, reports.len());
+        assert_eq!(0, reports[0].error_nr);
+        assert_eq!(1, reports[1].error_nr);
+        assert_eq!(CircuitState::Closed, reports[1].state);
+    }
+
+    #[test]
+    fn maps_outcomes_to_control_flow() {
+        let config = *Config::default().error_threshold(1);
+        let mut cmd = TestCommand::<bool, u8>::define(config, |ok| {
+            if ok {
+                Ok(5)
+            } else {
+                Err(TestError::Internal)
+            }
+        })
+        .unwrap();
+
+        assert_eq!(ControlFlow::Continue(5), cmd.run_control(true));
+        assert_eq!(
+            ControlFlow::Break(TestError::Internal),
+            cmd.run_control(false)
+        );
+        assert_eq!(
+            ControlFlow::Break(TestError::External),
+            cmd.run_control(true)
+        );
+    }
+
+    #[test]
+    fn chains_control_flow_with_question_mark() {
+        fn pipeline(cmd: &mut TestCommand<u8, u8>) -> ControlFlow<TestError, u8> {
+            let first = cmd.run_control(1)?;
+            let second = cmd.run_control(first + 1)?;
+            ControlFlow::Continue(second)
+        }
+
+        let mut cmd = TestCommand::<u8, u8>::define(Config::default(), |n| {
+            if n < 2 {
+                Ok(n)
+            } else {
+                Err(TestError::Internal)
+            }
+        })
+        .unwrap();
+
+        assert_eq!(ControlFlow::Break(TestError::Internal), pipeline(&mut cmd));
+    }
+
+    #[test]
+    fn returns_fallback_on_open_circuit_by_default() {
+        let config = *Config::default().error_threshold(1);
+        let mut cmd =
+            Command::define_with_fallback(config, |_| Err(TestError::Internal), |_| 5).unwrap();
+
+        assert_eq!(5, cmd.run(()).unwrap()); // Fallback by returned error
+        assert_eq!(5, cmd.run(()).unwrap()); // Fallback by reject error
+    }
+
+    #[test]
+    fn returns_rejection_on_open_circuit_if_preferred() {
+        let config = *Config::default()
+            .error_threshold(1)
+            .prefer_error_on_open(true);
+        let mut cmd =
+            Command::define_with_fallback(config, |_| Err(TestError::Internal), |_| 5).unwrap();
+
+        assert_eq!(5, cmd.run(()).unwrap()); // Fallback by returned error
+        assert_eq!(TestError::External, cmd.run(()).unwrap_err());
+    }
+
+    #[derive(Default)]
+    struct RequestIdObserver {
+        failed_requests: Mutex<Vec<u32>>,
+    }
+
+    impl Observer for RequestIdObserver {
+        fn on_failure_with_context(&self, context: &dyn Any) {
+            if let Some(&request_id) = context.downcast_ref::<u32>() {
+                self.failed_requests.lock().unwrap().push(request_id);
+            }
+        }
+    }
+
+    #[test]
+    fn passes_context_to_observers() {
+        let observer = Arc::new(RequestIdObserver::default());
+        let mut cmd = TestCommand::<bool, ()>::define(Config::default(), |ok| {
+            if ok {
+                Ok(())
+            } else {
+                Err(TestError::Internal)
+            }
+        })
+        .unwrap();
+        cmd.add_observer(observer.clone());
+
+        cmd.run_with_context(false, 1u32).unwrap_err();
+        cmd.run_with_context(true, 2u32).unwrap();
+        cmd.run_with_context(false, 3u32).unwrap_err();
+
+        assert_eq!(vec![1, 3], *observer.failed_requests.lock().unwrap());
+    }
+
+    #[test]
+    fn notifies_observers_without_context_by_default() {
+        let observer = Arc::new(RecordingObserver::default());
+        let mut cmd =
+            TestCommand::<(), ()>::define(Config::default(), |_| Err(TestError::Internal)).unwrap();
+        cmd.add_observer(observer.clone());
+
+        cmd.run_with_context((), "request-1").unwrap_err();
+
+        assert_eq!(vec!["failure"], *observer.events.lock().unwrap());
+    }
+
+    #[test]
+    fn intercepts_successful_outputs() {
+        let mut cmd = TestCommand::<u8, u8>::define_with_fallback(
+            Config::default(),
+            |n| {
+                if n > 0 {
+                    Ok(n)
+                } else {
+                    Err(TestError::Internal)
+                }
+            },
+            |_| 0,
+        )
+        .unwrap();
+        cmd.intercept(|n| n * 10);
+
+        assert_eq!(Ok(10), cmd.run(1));
+        assert_eq!(Ok(20), cmd.run(2));
+        assert_eq!(Ok(0), cmd.run(0));
+
+        let metrics = cmd.metrics();
+        assert_eq!(2, metrics.success_nr);
+        assert_eq!(1, metrics.error_nr);
+    }
+
+    #[test]
+    fn adapts_open_duration_to_failed_probes() {
+        let clock = Arc::new(ManualClock::new());
+        let config = *Config::default()
+            .error_threshold(1)
+            .adaptive_open_duration(true)
+            .min_open_ms(1000)
+            .max_open_ms(3000);
+        let mut cmd = TestCommand::<(), ()>::define(config, |_| Err(TestError::Internal)).unwrap();
+        cmd.clock(clock.clone());
+
+        assert_eq!(TestError::Internal, cmd.run(()).unwrap_err());
+        assert_eq!(TestError::External, cmd.run(()).unwrap_err());
+        assert_eq!(time::Duration::from_millis(1000), cmd.open_duration());
+
+        // Every failed probe doubles the open duration up to the
+        // maximum:
+        clock.advance(time::Duration::from_millis(1000));
+        assert_eq!(TestError::Internal, cmd.run(()).unwrap_err());
+        assert_eq!(time::Duration::from_millis(2000), cmd.open_duration());
+
+        clock.advance(time::Duration::from_millis(1000));
+        assert_eq!(TestError::External, cmd.run(()).unwrap_err());
+        clock.advance(time::Duration::from_millis(1000));
+        assert_eq!(TestError::Internal, cmd.run(()).unwrap_err());
+        assert_eq!(time::Duration::from_millis(3000), cmd.open_duration());
+    }
+
+    #[test]
+    fn resets_open_duration_after_recovery() {
+        let clock = Arc::new(ManualClock::new());
+        let config = *Config::default()
+            .error_threshold(1)
+            .adaptive_open_duration(true)
+            .min_open_ms(1000)
+            .max_open_ms(8000);
+        let mut cmd = TestCommand::<bool, ()>::define(config, |ok| {
+            if ok {
+                Ok(())
+            } else {
+                Err(TestError::Internal)
+            }
+        })
+        .unwrap();
+        cmd.clock(clock.clone());
+
+        cmd.run(false).unwrap_err();
+        cmd.run(false).unwrap_err();
+        clock.advance(time::Duration::from_millis(1000));
+        cmd.run(false).unwrap_err();
+        assert_eq!(time::Duration::from_millis(2000), cmd.open_duration());
+
+        clock.advance(time::Duration::from_millis(2000));
+        assert_eq!(Ok(()), cmd.run(true));
+        assert_eq!(time::Duration::from_millis(1000), cmd.open_duration());
+    }
+
+    #[test]
+    fn reset_restores_initial_state() {
+        let clock = Arc::new(ManualClock::new());
+        let config = *Config::default()
+            .error_threshold(1)
+            .adaptive_open_duration(true)
+            .min_open_ms(1000)
+            .max_open_ms(8000)
+            .reporting_buckets_in_window(Some(60));
+        let mut cmd = TestCommand::<(), ()>::define(config, |_| Err(TestError::Internal)).unwrap();
+        cmd.clock(clock.clone());
+
+        cmd.run(()).unwrap_err();
+        cmd.run(()).unwrap_err();
+        clock.advance(time::Duration::from_millis(1000));
+        cmd.run(()).unwrap_err();
+        assert_eq!(time::Duration::from_millis(2000), cmd.open_duration());
+
+        cmd.reset();
+        assert_eq!(time::Duration::from_millis(1000), cmd.open_duration());
+        assert_eq!(0, cmd.metrics().error_nr);
+        assert_eq!(0.0, cmd.rejection_rate());
+        assert_eq!(0.0, cmd.user_visible_success_rate());
+    }
+
+    #[test]
+    fn reuses_cached_metrics_until_stale() {
+        let clock = Arc::new(ManualClock::new());
+        let mut cmd =
+            TestCommand::<(), ()>::define(Config::default(), |_| Err(TestError::Internal)).unwrap();
+        cmd.clock(clock.clone());
+        let max_staleness = time::Duration::from_millis(100);
+
+        cmd.run(()).unwrap_err();
+        assert_eq!(1, cmd.metrics_cached(max_staleness).error_nr);
+
+        cmd.run(()).unwrap_err();
+        clock.advance(time::Duration::from_millis(50));
+        assert_eq!(1, cmd.metrics_cached(max_staleness).error_nr);
+
+        clock.advance(time::Duration::from_millis(51));
+        assert_eq!(2, cmd.metrics_cached(max_staleness).error_nr);
+    }
+
+    #[test]
+    fn breaker_can_be_shared_between_threads() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<CircuitBreaker>();
+    }
+
+    #[test]
+    fn opens_circuit_on_failure_burst() {
+        let clock = Arc::new(ManualClock::new());
+        let config = *Config::default()
+            .error_threshold(100)
+            .burst_threshold(Some((3, time::Duration::from_millis(200))));
+        let mut cmd = TestCommand::<(), ()>::define(config, |_| Err(TestError::Internal)).unwrap();
+        cmd.clock(clock.clone());
+
+        // Spread out failures don't trip the breaker:
+        for _ in 0..3 {
+            assert_eq!(TestError::Internal, cmd.run(()).unwrap_err());
+            clock.advance(time::Duration::from_millis(250));
+        }
+
+        // But a tight burst does:
+        for _ in 0..3 {
+            assert_eq!(TestError::Internal, cmd.run(()).unwrap_err());
+            clock.advance(time::Duration::from_millis(10));
+        }
+        assert_eq!(CircuitState::Open, cmd.metrics().state);
+        assert_eq!(TestError::External, cmd.run(()).unwrap_err());
+    }
+
+    #[test]
+    fn pairs_errors_with_breaker_state() {
+        let config = *Config::default().error_threshold(1);
+        let mut cmd = TestCommand::<(), ()>::define(config, |_| Err(TestError::Internal)).unwrap();
+
+        assert_eq!(
+            Err((TestError::Internal, CircuitState::Closed)),
+            cmd.run_with_state(())
+        );
+        assert_eq!(
+            Err((TestError::External, CircuitState::Open)),
+            cmd.run_with_state(())
+        );
+    }
+
+    #[test]
+    fn pairs_failed_probe_with_closed_state() {
+        let clock = Arc::new(ManualClock::new());
+        let config = *Config::default()
+            .error_threshold(1)
+            .circuit_open_ms(1000)
+            .adaptive_open_duration(true);
+        let mut cmd = TestCommand::<(), ()>::define(config, |_| Err(TestError::Internal)).unwrap();
+        cmd.clock(clock.clone());
+
+        cmd.run(()).unwrap_err();
+        cmd.run(()).unwrap_err();
+        assert_eq!(CircuitState::Open, cmd.metrics().state);
+
+        // The adaptive probe fails and reopens the circuit, but was admitted:
+        clock.advance(time::Duration::from_millis(1001));
+        assert_eq!(
+            Err((TestError::Internal, CircuitState::Closed)),
+            cmd.run_with_state(())
+        );
+        assert_eq!(CircuitState::Open, cmd.metrics().state);
+        assert_eq!(
+            Err((TestError::External, CircuitState::Open)),
+            cmd.run_with_state(())
+        );
+    }
+
+    #[test]
+    fn reports_metrics_from_longer_window() {
+        let clock = Arc::new(ManualClock::new());
+        let config = *Config::default()
+            .error_threshold(2)
+            .buckets_in_window(2)
+            .bucket_size_in_ms(1000)
+            .circuit_open_ms(1000)
+            .reporting_buckets_in_window(Some(60));
+        let mut cmd = TestCommand::<bool, ()>::define(config, |ok| {
+            if ok {
+                Ok(())
+            } else {
+                Err(TestError::Internal)
+            }
+        })
+        .unwrap();
+        cmd.clock(clock.clone());
+
+        cmd.run(true).unwrap();
+        clock.advance(time::Duration::from_secs(5));
+
+        // The success has left the short window, so two failures trip
+        // the breaker:
+        cmd.run(false).unwrap_err();
+        cmd.run(false).unwrap_err();
+        assert_eq!(TestError::External, cmd.run(true).unwrap_err());
+
+        let metrics = cmd.metrics();
+        assert_eq!(CircuitState::Open, metrics.state);
+        assert_eq!(1, metrics.success_nr);
+        assert_eq!(2, metrics.error_nr);
+    }
+
+    #[test]
+    fn small_window_trips_on_error_count() {
+        let config = *Config::default()
+            .buckets_in_window(1)
+            .bucket_size_in_ms(100)
+            .circuit_open_ms(100);
+        let mut cmd = TestCommand::<(), ()>::define(config, |_| Err(TestError::Internal)).unwrap();
+
+        for _ in 0..10 {
+            assert_eq!(TestError::Internal, cmd.run(()).unwrap_err());
+        }
+        assert_eq!(TestError::External, cmd.run(()).unwrap_err());
+    }
+
+    #[test]
+    fn defers_probes_beyond_budget() {
+        let clock = Arc::new(ManualClock::new());
+        let config = *Config::default()
+            .error_threshold(1)
+            .circuit_open_ms(1000)
+            .probe_budget(Some((1, time::Duration::from_secs(10))));
+        let mut cmd = TestCommand::<(), ()>::define(config, |_| Err(TestError::Internal)).unwrap();
+        cmd.clock(clock.clone());
+
+        assert_eq!(TestError::Internal, cmd.run(()).unwrap_err());
+        assert_eq!(TestError::External, cmd.run(()).unwrap_err());
+
+        clock.advance(time::Duration::from_secs(1));
+        assert_eq!(TestError::Internal, cmd.run(()).unwrap_err());
+        assert_eq!(TestError::External, cmd.run(()).unwrap_err());
+
+        // The open duration elapsed, but the budget is used up:
+        clock.advance(time::Duration::from_secs(1));
+        assert_eq!(TestError::External, cmd.run(()).unwrap_err());
+        assert_eq!(CircuitState::Open, cmd.metrics().state);
+
+        clock.advance(time::Duration::from_secs(9));
+        assert_eq!(TestError::Internal, cmd.run(()).unwrap_err());
+    }
+
+    #[test]
+    fn replays_recorded_outcomes() {
+        let config = *Config::default().error_threshold(2).circuit_open_ms(1000);
+        let mut breaker = CircuitBreaker::new(config).unwrap();
+        let start = time::Instant::now();
+        let at = |ms| start + time::Duration::from_millis(ms);
+
+        let timeline = breaker.replay(&[
+            (Point::SUCCESS, at(0)),
+            (Point::FAILURE, at(100)),
+            (Point::FAILURE, at(200)),
+            (Point::FAILURE, at(300)),
+            (Point::SUCCESS, at(800)),
+            (Point::SUCCESS, at(1400)),
+        ]);
+
+        assert_eq!(
+            vec![
+                (at(300), CircuitState::Open),
+                (at(1400), CircuitState::Closed)
+            ],
+            timeline
+        );
+    }
+
+    #[test]
+    fn counts_errors_of_recent_buckets_only() {
+        let clock = Arc::new(ManualClock::new());
+        let config = *Config::default()
+            .error_threshold(3)
+            .buckets_in_window(10)
+            .bucket_size_in_ms(1000)
+            .count_window_buckets(Some(1));
+        let mut cmd = TestCommand::<(), ()>::define(config, |_| Err(TestError::Internal)).unwrap();
+        cmd.clock(clock.clone());
+
+        // Errors spread across the window don't reach the threshold:
+        for _ in 0..3 {
+            assert_eq!(TestError::Internal, cmd.run(()).unwrap_err());
+            clock.advance(time::Duration::from_secs(3));
+        }
+        assert_eq!(TestError::Internal, cmd.run(()).unwrap_err());
+
+        // But a burst within the most recent bucket does:
+        assert_eq!(TestError::Internal, cmd.run(()).unwrap_err());
+        assert_eq!(TestError::Internal, cmd.run(()).unwrap_err());
+        assert_eq!(TestError::External, cmd.run(()).unwrap_err());
+    }
+
+    #[test]
+    fn groups_metrics_by_tag() {
+        let mut cmd = TestCommand::<bool, ()>::define(Config::default(), |ok| {
+            if ok {
+                Ok(())
+            } else {
+                Err(TestError::Internal)
+            }
+        })
+        .unwrap();
+
+        cmd.run_tagged(true, "eu").unwrap();
+        cmd.run_tagged(false, "eu").unwrap_err();
+        cmd.run_tagged(false, "us").unwrap_err();
+        cmd.run(true).unwrap();
+
+        let metrics = cmd.metrics_by_tag();
+        assert_eq!(2, metrics.len());
+        assert_eq!(1, metrics["eu"].success_nr);
+        assert_eq!(1, metrics["eu"].error_nr);
+        assert_eq!(50, metrics["eu"].error_percentage);
+        assert_eq!(0, metrics["us"].success_nr);
+        assert_eq!(1, metrics["us"].error_nr);
+        assert_eq!(2, cmd.metrics().success_nr);
+    }
+
+    /// Resource of a mock pool, returned to the pool when dropped.
+    struct PooledResource {
+        checked_out: Rc<Cell<u32>>,
+        healthy: bool,
+    }
+
+    impl Drop for PooledResource {
+        fn drop(&mut self) {
+            self.checked_out.set(self.checked_out.get() - 1);
+        }
+    }
+
+    #[test]
+    fn returns_pooled_resources_on_rejection() {
+        let checked_out = Rc::new(Cell::new(0));
+        let acquisitions = Cell::new(0);
+        let acquire = |healthy| {
+            acquisitions.set(acquisitions.get() + 1);
+            checked_out.set(checked_out.get() + 1);
+            Ok(PooledResource {
+                checked_out: checked_out.clone(),
+                healthy,
+            })
+        };
+
+        let config = *Config::default().error_threshold(1);
+        let mut cmd = TestCommand::<PooledResource, ()>::define(config, |resource| {
+            if resource.healthy {
+                Ok(())
+            } else {
+                Err(TestError::Internal)
+            }
+        })
+        .unwrap();
+
+        assert_eq!(Ok(()), cmd.run_with_resource(|| acquire(true)));
+        assert_eq!(
+            TestError::Internal,
+            cmd.run_with_resource(|| acquire(false)).unwrap_err()
+        );
+        assert_eq!(
+            TestError::External,
+            cmd.run_with_resource(|| acquire(true)).unwrap_err()
+        );
+
+        assert_eq!(2, acquisitions.get());
+        assert_eq!(0, checked_out.get());
+    }
+
+    #[test]
+    fn records_invalid_pooled_resources_as_failures() {
+        let checked_out = Rc::new(Cell::new(0));
+        let acquire = || {
+            checked_out.set(checked_out.get() + 1);
+            Ok(PooledResource {
+                checked_out: checked_out.clone(),
+                healthy: false,
+            })
+        };
+
+        let config = *Config::default().error_threshold(1);
+        let mut cmd = TestCommand::<PooledResource, ()>::define(config, |_| Ok(())).unwrap();
+        cmd.validate(|resource| resource.healthy);
+
+        assert_eq!(
+            TestError::External,
+            cmd.run_with_resource(acquire).unwrap_err()
+        );
+        assert_eq!(1, cmd.metrics().error_nr);
+        assert_eq!(0, checked_out.get());
+
+        // The failure opens the circuit like any other:
+        assert_eq!(
+            TestError::External,
+            cmd.run_with_resource(acquire).unwrap_err()
+        );
+        assert_eq!(CircuitState::Open, cmd.metrics().state);
+    }
+
+    #[test]
+    fn opens_circuit_on_consecutive_failures() {
+        let config = *Config::default().mode(BreakerMode::ConsecutiveFailures(3));
+        let mut cmd = TestCommand::<bool, ()>::define(config, |ok| {
+            if ok {
+                Ok(())
+            } else {
+                Err(TestError::Internal)
+            }
+        })
+        .unwrap();
+
+        // A single success resets the streak:
+        cmd.run(false).unwrap_err();
+        cmd.run(false).unwrap_err();
+        cmd.run(true).unwrap();
+        cmd.run(false).unwrap_err();
+        cmd.run(false).unwrap_err();
+        assert_eq!(Ok(()), cmd.run(true));
+
+        for _ in 0..3 {
+            assert_eq!(TestError::Internal, cmd.run(false).unwrap_err());
+        }
+        assert_eq!(TestError::External, cmd.run(true).unwrap_err());
+    }
+
+    #[test]
+    fn sheds_calls_in_proportion_to_error_rate() {
+        let config = *Config::default()
+            .error_threshold(1000)
+            .graduated_shedding(true);
+        let mut breaker = CircuitBreaker::new(config).unwrap();
+        breaker.set_rng(Arc::new(XorShiftRng::with_seed(42)));
+
+        let admitted_share = |breaker: &mut CircuitBreaker| {
+            let admitted = (0..10_000)
+                .filter(|_| breaker.check_command_allowed())
+                .count();
+            admitted as f64 / 10_000.0
+        };
+
+        assert_eq!(1.0, admitted_share(&mut breaker));
+
+        for i in 0..10 {
+            let result: Result<(), ()> = if i < 3 { Err(()) } else { Ok(()) };
+            breaker.register_result(&result);
+        }
+        assert!((admitted_share(&mut breaker) - 0.7).abs() < 0.02);
+    }
+
+    #[test]
+    fn latched_circuit_stays_open_until_reset() {
+        let clock = Arc::new(ManualClock::new());
+        let config = *Config::default()
+            .error_threshold(1)
+            .circuit_open_ms(1000)
+            .latch_open(true);
+        let mut cmd = TestCommand::<bool, ()>::define(config, |ok| {
+            if ok {
+                Ok(())
+            } else {
+                Err(TestError::Internal)
+            }
+        })
+        .unwrap();
+        cmd.clock(clock.clone());
+
+        assert_eq!(TestError::Internal, cmd.run(false).unwrap_err());
+        assert_eq!(TestError::External, cmd.run(true).unwrap_err());
+
+        clock.advance(time::Duration::from_secs(60));
+        assert_eq!(TestError::External, cmd.run(true).unwrap_err());
+
+        cmd.reset();
+        assert_eq!(CircuitState::Closed, cmd.metrics().state);
+        assert_eq!(Ok(()), cmd.run(true));
+    }
+
+    #[test]
+    fn returns_error_if_fallback_panics() {
+        let config = *Config::default().error_threshold(1);
+        let mut cmd = TestCommand::<(), u8>::define_with_fallback(
+            config,
+            |_| Err(TestError::Internal),
+            |_| panic!("Fallback failed"),
+        )
+        .unwrap();
+
+        // On failure of the command:
+        assert_eq!(Err(TestError::External), cmd.run(()));
+
+        // And on an open circuit:
+        assert_eq!(CircuitState::Open, cmd.run_with_state(()).unwrap_err().1);
+        assert_eq!(0.0, cmd.user_visible_success_rate());
+    }
+
+    #[test]
+    fn bypasses_open_circuit() {
+        let config = *Config::default().error_threshold(1);
+        let mut cmd = TestCommand::<bool, ()>::define(config, |ok| {
+            if ok {
+                Ok(())
+            } else {
+                Err(TestError::Internal)
+            }
+        })
+        .unwrap();
+
+        cmd.run(false).unwrap_err();
+        assert_eq!(TestError::External, cmd.run(true).unwrap_err());
+
+        assert_eq!(Ok(()), cmd.run_bypass(true));
+        assert_eq!(TestError::Internal, cmd.run_bypass(false).unwrap_err());
+
+        let metrics = cmd.metrics();
+        assert_eq!(CircuitState::Open, metrics.state);
+        assert_eq!(1, metrics.success_nr);
+        assert_eq!(1, metrics.error_nr);
+    }
+
+    #[derive(Debug)]
+    struct FixedRng(f64);
+
+    impl Rng for FixedRng {
+        fn next_f64(&self) -> f64 {
+            self.0
+        }
+    }
+
+    #[test]
+    fn raises_tiny_open_durations_to_jittered_floor() {
+        let clock = Arc::new(ManualClock::new());
+        let config = *Config::default()
+            .error_threshold(1)
+            .circuit_open_ms(1)
+            .open_duration_floor_ms(10);
+        let mut cmd = TestCommand::<(), ()>::define(config, |_| Err(TestError::Internal)).unwrap();
+        cmd.clock(clock.clone());
+        cmd.rng(Arc::new(FixedRng(0.5)));
+        assert_eq!(time::Duration::from_millis(1), cmd.open_duration());
+
+        cmd.run(()).unwrap_err();
+        assert_eq!(TestError::External, cmd.run(()).unwrap_err());
+        assert_eq!(time::Duration::from_millis(15), cmd.open_duration());
+
+        clock.advance(time::Duration::from_millis(14));
+        assert_eq!(TestError::External, cmd.run(()).unwrap_err());
+
+        clock.advance(time::Duration::from_millis(1));
+        assert_eq!(TestError::Internal, cmd.run(()).unwrap_err());
+    }
+
+    #[test]
+    fn keeps_tiny_open_durations_without_floor() {
+        let clock = Arc::new(ManualClock::new());
+        let config = *Config::default()
+            .error_threshold(1)
+            .circuit_open_ms(1)
+            .open_duration_floor_ms(0);
+        let mut cmd = TestCommand::<(), ()>::define(config, |_| Err(TestError::Internal)).unwrap();
+        cmd.clock(clock.clone());
+
+        cmd.run(()).unwrap_err();
+        assert_eq!(TestError::External, cmd.run(()).unwrap_err());
+
+        clock.advance(time::Duration::from_millis(1));
+        assert_eq!(TestError::Internal, cmd.run(()).unwrap_err());
+    }
+
+    #[test]
+    fn falls_back_only_for_matching_errors() {
+        let mut cmd =
+            TestCommand::<TestError, u8>::define_with_fallback(Config::default(), Err, |_| 5)
+                .unwrap();
+        cmd.fallback_predicate(|err| *err == TestError::External);
+
+        assert_eq!(Ok(5), cmd.run(TestError::External));
+        assert_eq!(Err(TestError::Internal), cmd.run(TestError::Internal));
+    }
+
+    #[test]
+    fn does_not_open_circuit_during_warmup() {
+        let clock = Arc::new(ManualClock::new());
+        let config = *Config::default().error_threshold(3).warmup_ms(Some(1000));
+        let mut cmd = TestCommand::<(), ()>::define(config, |_| Err(TestError::Internal)).unwrap();
+        cmd.clock(clock.clone());
+
+        for _ in 0..10 {
+            assert_eq!(TestError::Internal, cmd.run(()).unwrap_err());
+        }
+
+        clock.advance(time::Duration::from_millis(1000));
+        assert_eq!(TestError::External, cmd.run(()).unwrap_err());
+    }
+
+    #[test]
+    fn computes_latency_percentile_from_most_recent_latencies() {
+        let config = *Config::default().latency_trend_guard(Some(LatencyGuard {
+            p: 0.9,
+            threshold_ms: 100,
+        }));
+        let mut breaker = CircuitBreaker::new(config).unwrap();
+
+        for _ in 0..1024 {
+            breaker.register_latency(time::Duration::from_millis(500));
+        }
+        for _ in 0..1024 {
+            breaker.register_latency(time::Duration::from_millis(5));
+        }
+
+        assert_eq!(
+            Some(time::Duration::from_millis(5)),
+            breaker.latency_percentile(1.0)
+        );
+    }
+
+    #[test]
+    fn latency_guard_routes_to_fallback_once_percentile_exceeds_threshold() {
+        let clock = Arc::new(ManualClock::new());
+        let config = *Config::default().latency_trend_guard(Some(LatencyGuard {
+            p: 0.9,
+            threshold_ms: 100,
+        }));
+
+        // Calls take as many ms as their input says:
+        let call_clock = clock.clone();
+        let mut cmd = Command::<u64, u64, TestError, _, _>::define_with_fallback(
+            config,
+            move |latency_ms| {
+                call_clock.advance(time::Duration::from_millis(latency_ms));
+                Ok(latency_ms)
+            },
+            |_| 0,
+        )
+        .unwrap();
+        cmd.clock(clock.clone());
+
+        for &latency_ms in &[50, 70, 90, 130] {
+            assert_eq!(Ok(latency_ms), cmd.run(latency_ms));
+        }
+
+        // The 90th percentile of the latencies is now 130ms:
+        assert_eq!(Ok(0), cmd.run(10));
+        assert_eq!(CircuitState::Closed, cmd.metrics().state);
+
+        // Calls go through again once the slow calls left the window:
+        clock.advance(time::Duration::from_millis(10000));
+        assert_eq!(Ok(10), cmd.run(10));
+    }
+
+    #[test]
+    fn commands_sharing_a_window_trip_together() {
+        let config = *Config::default().error_threshold(4);
+        let window = Arc::new(Mutex::new(Window::new(config).unwrap()));
+
+        let mut first =
+            TestCommand::<(), ()>::define(config, |_| Err(TestError::Internal)).unwrap();
+        first.share_window(window.clone());
+        let mut second =
+            TestCommand::<(), ()>::define(config, |_| Err(TestError::Internal)).unwrap();
+        second.share_window(window.clone());
+
+        // Each command only records two failures on its own:
+        for _ in 0..2 {
+            assert_eq!(TestError::Internal, first.run(()).unwrap_err());
+            assert_eq!(TestError::Internal, second.run(()).unwrap_err());
+        }
+
+        assert_eq!(TestError::External, first.run(()).unwrap_err());
+        assert_eq!(TestError::External, second.run(()).unwrap_err());
+    }
+
+    #[test]
+    fn serves_at_most_max_total_calls_until_reset() {
+        let config = *Config::default().max_total_calls(Some(3));
+        let mut cmd = TestCommand::<(), u8>::define(config, |_| Ok(1)).unwrap();
+
+        for _ in 0..3 {
+            assert_eq!(Ok(1), cmd.run(()));
+        }
+
+        for _ in 0..3 {
+            assert_eq!(Err(TestError::External), cmd.run(()));
+        }
+        assert_eq!(CircuitState::Open, cmd.metrics().state);
+
+        cmd.reset();
+        assert_eq!(Ok(1), cmd.run(()));
+    }
+
+    #[test]
+    fn run_degradable_marks_fallback_values_as_degraded() {
+        let config = *Config::default().error_threshold(2);
+        let mut cmd = TestCommand::<bool, u8>::define_with_fallback(
+            config,
+            |ok| if ok { Ok(1) } else { Err(TestError::Internal) },
+            |_| 0,
+        )
+        .unwrap();
+
+        assert_eq!(Ok(Degradable::Fresh(1)), cmd.run_degradable(true));
+        assert_eq!(
+            Ok(Degradable::Degraded(0, DegradationReason::Failed)),
+            cmd.run_degradable(false)
+        );
+        cmd.run_degradable(false).unwrap();
+
+        assert_eq!(
+            Ok(Degradable::Degraded(0, DegradationReason::Rejected)),
+            cmd.run_degradable(true)
+        );
+    }
+
+    #[test]
+    fn zero_percent_threshold_trips_on_first_failure() {
+        let config = *Config::default()
+            .error_threshold(1)
+            .error_threshold_percentage(0);
+        let mut cmd = TestCommand::<bool, u8>::define(config, |ok| {
+            if ok {
+                Ok(1)
+            } else {
+                Err(TestError::Internal)
+            }
+        })
+        .unwrap();
+
+        for _ in 0..10 {
+            assert_eq!(Ok(1), cmd.run(true));
+        }
+
+        assert_eq!(Err(TestError::Internal), cmd.run(false));
+        assert_eq!(Err(TestError::External), cmd.run(true));
+    }
+
+    #[test]
+    fn zero_thresholds_do_not_trip_without_failures() {
+        let config = *Config::default()
+            .error_threshold(0)
+            .error_threshold_percentage(0);
+        let mut cmd = TestCommand::<(), u8>::define(config, |_| Ok(1)).unwrap();
+
+        for _ in 0..3 {
+            assert_eq!(Ok(1), cmd.run(()));
+        }
+    }
+
+    #[test]
+    fn computes_rejection_rate() {
+        let config = *Config::default().error_threshold(2);
+        let mut cmd = TestCommand::<(), ()>::define(config, |_| Err(TestError::Internal)).unwrap();
+        assert_eq!(0.0, cmd.rejection_rate());
+
+        for _ in 0..2 {
+            assert_eq!(TestError::Internal, cmd.run(()).unwrap_err());
+        }
+        for _ in 0..3 {
+            assert_eq!(TestError::External, cmd.run(()).unwrap_err());
+        }
+
+        // Two admitted and three rejected calls:
+        assert_eq!(0.6, cmd.rejection_rate());
+    }
+
+    #[test]
+    fn sheds_writes_at_lower_error_rate_than_reads() {
+        let config = *Config::default()
+            .error_threshold(2)
+            .error_threshold_percentage(90)
+            .read_error_threshold_percentage(Some(75))
+            .write_error_threshold_percentage(Some(40));
+        let mut cmd = TestCommand::<bool, u8>::define(config, |ok| {
+            if ok {
+                Ok(1)
+            } else {
+                Err(TestError::Internal)
+            }
+        })
+        .unwrap();
+
+        for &ok in &[true, true, false, false] {
+            cmd.run_classified(ok, OperationClass::Read).ok();
+        }
+
+        // At an error rate of 50%, only writes are shed:
+        assert_eq!(
+            Err(TestError::External),
+            cmd.run_classified(true, OperationClass::Write)
+        );
+        assert_eq!(Ok(1), cmd.run_classified(true, OperationClass::Read));
+        assert_eq!(CircuitState::Closed, cmd.metrics().state);
+
+        // Failed reads raise the error rate to 75%, shedding reads too:
+        for _ in 0..7 {
+            cmd.run_classified(false, OperationClass::Read).ok();
+        }
+        assert_eq!(
+            Err(TestError::External),
+            cmd.run_classified(true, OperationClass::Read)
+        );
+    }
+
+    thread_local! {
+        static DECISIONS: RefCell<Vec<Decision>> = const { RefCell::new(Vec::new()) };
+    }
+
+    fn record_decision(decision: Decision) {
+        DECISIONS.with(|decisions| decisions.borrow_mut().push(decision));
+    }
+
+    #[test]
+    fn reports_decisions_before_running() {
+        let config = *Config::default().error_threshold(2);
+        let mut cmd = TestCommand::<(), ()>::define(config, |_| {
+            // The decision was reported before the command runs:
+            let decided = DECISIONS.with(|decisions| decisions.borrow().len());
+            assert!(decided > 0);
+            Err(TestError::Internal)
+        })
+        .unwrap();
+        cmd.on_decision(record_decision);
+
+        for _ in 0..4 {
+            cmd.run(()).unwrap_err();
+        }
+
+        let rejected = Decision::Rejected(RejectionReason::CircuitOpen);
+        assert_eq!(
+            vec![Decision::Allowed, Decision::Allowed, rejected, rejected],
+            DECISIONS.with(|decisions| decisions.borrow().clone())
+        );
+    }
+
+    #[derive(Default)]
+    struct OutcomeObserver {
+        outcomes: Mutex<Vec<Outcome>>,
+    }
+
+    impl Observer for OutcomeObserver {
+        fn on_outcome(&self, outcome: Outcome) {
+            self.outcomes.lock().unwrap().push(outcome);
+        }
+    }
+
+    #[test]
+    fn notifies_outcome_once_per_call() {
+        let observer = Arc::new(OutcomeObserver::default());
+        let config = *Config::default().error_threshold(1);
+        let mut cmd = TestCommand::<u8, u8>::define(config, |n| {
+            if n > 0 {
+                Ok(n)
+            } else {
+                Err(TestError::Internal)
+            }
+        })
+        .unwrap();
+        cmd.validate(|&n| n < 100).add_observer(observer.clone());
+
+        cmd.run(1).unwrap();
+        cmd.run(100).unwrap_err();
+        cmd.run(0).unwrap_err();
+        cmd.run(1).unwrap_err();
+
+        assert_eq!(
+            vec![
+                Outcome::Success,
+                Outcome::InvalidInput,
+                Outcome::Failure,
+                Outcome::Rejected,
+            ],
+            *observer.outcomes.lock().unwrap()
+        );
+    }
+
+    #[test]
+    fn notifies_outcome_while_disabled() {
+        let observer = Arc::new(OutcomeObserver::default());
+        let config = *Config::default().circuit_breaker_enabled(false);
+        let mut cmd = TestCommand::<u8, u8>::define(config, |n| {
+            if n > 0 {
+                Ok(n)
+            } else {
+                Err(TestError::Internal)
+            }
+        })
+        .unwrap();
+        cmd.validate(|&n| n < 100).add_observer(observer.clone());
+
+        cmd.run(1).unwrap();
+        cmd.run(100).unwrap_err();
+        cmd.run_degradable(0).unwrap_err();
+        cmd.run_with_resource(|| Ok(0)).unwrap_err();
+        cmd.run_traced(1).0.unwrap();
+
+        assert_eq!(
+            vec![
+                Outcome::Success,
+                Outcome::InvalidInput,
+                Outcome::Failure,
+                Outcome::Failure,
+                Outcome::Success,
+            ],
+            *observer.outcomes.lock().unwrap()
+        );
+    }
+
+    #[test]
+    fn reopens_at_expiry_if_window_is_still_bad() {
+        let clock = Arc::new(ManualClock::new());
+        let config = *Config::default().error_threshold(2).circuit_open_ms(1000);
+        let mut cmd = TestCommand::<bool, u8>::define(config, |ok| {
+            if ok {
+                Ok(1)
+            } else {
+                Err(TestError::Internal)
+            }
+        })
+        .unwrap();
+        cmd.clock(clock.clone());
+
+        for _ in 0..2 {
+            cmd.run(false).unwrap_err();
+        }
+        assert_eq!(Err(TestError::External), cmd.run(true));
+
+        // Failures of bypassing calls keep the window bad while open:
+        for _ in 0..2 {
+            cmd.run_bypass(false).unwrap_err();
+        }
+
+        clock.advance(time::Duration::from_millis(1000));
+        assert_eq!(Err(TestError::External), cmd.run(true));
+        assert_eq!(CircuitState::Open, cmd.metrics().state);
+
+        // Reopening cleared the window, so the next expiry closes:
+        clock.advance(time::Duration::from_millis(999));
+        assert_eq!(Err(TestError::External), cmd.run(true));
+        clock.advance(time::Duration::from_millis(1));
+        assert_eq!(Ok(1), cmd.run(true));
+    }
+
+    #[test]
+    fn closes_at_expiry_if_window_is_good() {
+        let clock = Arc::new(ManualClock::new());
+        let config = *Config::default().error_threshold(2).circuit_open_ms(1000);
+        let mut cmd = TestCommand::<(), ()>::define(config, |_| Err(TestError::Internal)).unwrap();
+        cmd.clock(clock.clone());
+
+        for _ in 0..2 {
+            cmd.run(()).unwrap_err();
+        }
+        assert_eq!(Err(TestError::External), cmd.run(()));
+
+        clock.advance(time::Duration::from_millis(1000));
+        assert_eq!(Err(TestError::Internal), cmd.run(()));
+    }
+
+    #[test]
+    fn ramps_admission_up_after_recovery() {
+        const RAMP: &[(f64, time::Duration)] = &[
+            (0.1, time::Duration::from_secs(1)),
+            (0.5, time::Duration::from_secs(1)),
+        ];
+        let clock = Arc::new(ManualClock::new());
+        let config = *Config::default()
+            .error_threshold(2)
+            .circuit_open_ms(1000)
+            .recovery_ramp(Some(RAMP));
+        let mut breaker = CircuitBreaker::with_clock(config, clock.clone()).unwrap();
+        breaker.set_rng(Arc::new(XorShiftRng::with_seed(42)));
+
+        let admitted_share = |breaker: &mut CircuitBreaker| {
+            let admitted = (0..10_000)
+                .filter(|_| breaker.check_command_allowed())
+                .count();
+            admitted as f64 / 10_000.0
+        };
+
+        for _ in 0..2 {
+            breaker.register_result::<(), ()>(&Err(()));
+        }
+        assert!(!breaker.check_command_allowed());
+
+        // The probe after the open duration is admitted and succeeds:
+        clock.advance(time::Duration::from_millis(1000));
+        assert!(breaker.check_command_allowed());
+        breaker.register_result::<(), ()>(&Ok(()));
+
+        assert!((admitted_share(&mut breaker) - 0.1).abs() < 0.02);
+        clock.advance(time::Duration::from_secs(1));
+        assert!((admitted_share(&mut breaker) - 0.5).abs() < 0.02);
+
+        // A failure starts the ramp over:
+        breaker.register_result::<(), ()>(&Err(()));
+        assert_eq!(CircuitState::Closed, breaker.state());
+        assert!((admitted_share(&mut breaker) - 0.1).abs() < 0.02);
+
+        clock.advance(time::Duration::from_secs(2));
+        assert_eq!(1.0, admitted_share(&mut breaker));
+    }
+
+    #[test]
+    fn caps_fallbacks_of_rejected_calls() {
+        let clock = Arc::new(ManualClock::new());
+        let config = *Config::default()
+            .error_threshold(1)
+            .circuit_open_ms(60_000)
+            .max_fallback_rate(Some((2, time::Duration::from_secs(1))));
+        let mut cmd = TestCommand::<(), u8>::define_with_fallback(
+            config,
+            |_| Err(TestError::Internal),
+            |_| 0,
+        )
+        .unwrap();
+        cmd.clock(clock.clone());
+
+        // Failures of the command function are not capped:
+        assert_eq!(Ok(0), cmd.run(()));
+
+        assert_eq!(Ok(0), cmd.run(()));
+        assert_eq!(Ok(0), cmd.run(()));
+        assert_eq!(Err(TestError::External), cmd.run(()));
+        assert_eq!(Err(TestError::External), cmd.run(()));
+
+        clock.advance(time::Duration::from_secs(1));
+        assert_eq!(Ok(0), cmd.run(()));
+    }
+
+    #[test]
+    fn restores_exported_window() {
+        let clock = Arc::new(ManualClock::new());
+        let config = *Config::default().error_threshold(3);
+        let mut cmd = TestCommand::<bool, ()>::define(config, |fail| {
+            if fail {
+                Err(TestError::Internal)
+            } else {
+                Ok(())
+            }
+        })
+        .unwrap();
+        cmd.clock(clock.clone());
+        let _ = cmd.run(true);
+        clock.advance(time::Duration::from_millis(1500));
+        let _ = cmd.run(true);
+        let _ = cmd.run(false);
+        let export = cmd.export_window();
+        let error_rate = cmd.error_rate();
+
+        let mut other = TestCommand::<bool, ()>::define(config, |_| Ok(())).unwrap();
+        other.clock(clock.clone()).import_window(&export);
+        assert_eq!(export, other.export_window());
+        assert_eq!(error_rate, other.error_rate());
+
+        // One more failure reaches the threshold:
+        let _ = cmd.run(true);
+        other.import_window(&cmd.export_window());
+        assert_eq!(Err(TestError::External), other.run(false));
+    }
+
+    thread_local! {
+        static TOKENS: Cell<u32> = const { Cell::new(0) };
+    }
+
+    fn take_token() -> bool {
+        TOKENS.with(|tokens| match tokens.get() {
+            0 => false,
+            n => {
+                tokens.set(n - 1);
+                true
+            }
+        })
+    }
+
+    type RateLimitedCommand =
+        Command<bool, (), CriusError, fn(bool) -> Result<(), CriusError>, fn(CriusError)>;
+
+    fn fail_if(fail: bool) -> Result<(), CriusError> {
+        if fail {
+            Err(CriusError::InvalidConfig)
+        } else {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn rejects_calls_over_rate_limit() {
+        let config = *Config::default().error_threshold(2);
+        let mut cmd = RateLimitedCommand::define(config, fail_if).unwrap();
+        cmd.rate_limit(take_token);
+
+        TOKENS.with(|tokens| tokens.set(2));
+        assert!(cmd.run(false).is_ok());
+        assert!(matches!(cmd.run(true), Err(CriusError::InvalidConfig)));
+
+        // Over-limit calls neither run nor reach the window:
+        assert!(matches!(cmd.run(false), Err(CriusError::RateLimited)));
+        assert!(matches!(cmd.run(true), Err(CriusError::RateLimited)));
+        assert_eq!(0.5, cmd.error_rate());
+
+        // The rate limit applies while the circuit is open, too:
+        TOKENS.with(|tokens| tokens.set(2));
+        assert!(matches!(cmd.run(true), Err(CriusError::InvalidConfig)));
+        assert!(matches!(cmd.run(false), Err(CriusError::ExecutionRejected)));
+        assert!(matches!(cmd.run(false), Err(CriusError::RateLimited)));
+    }
+
+    fn classify_internal_as_success(err: &TestError) -> Result<Classification, ClassifierError> {
+        match *err {
+            TestError::Internal => Ok(Classification::Success),
+            TestError::External => Err(ClassifierError::new("unknown error")),
+        }
+    }
+
+    #[derive(Default)]
+    struct ClassifierErrorRecorder {
+        errors: Mutex<Vec<ClassifierError>>,
+    }
+
+    impl Observer for ClassifierErrorRecorder {
+        fn on_classifier_error(&self, error: &ClassifierError) {
+            self.errors.lock().unwrap().push(error.clone());
+        }
+    }
+
+    #[test]
+    fn records_errors_as_classified() {
+        let config = *Config::default().error_threshold(2);
+        let mut cmd = TestCommand::<TestError, ()>::define(config, Err).unwrap();
+        cmd.classify_failure(classify_internal_as_success);
+
+        assert_eq!(Err(TestError::Internal), cmd.run(TestError::Internal));
+        assert_eq!(Err(TestError::Internal), cmd.run(TestError::Internal));
+        assert_eq!(0.0, cmd.error_rate());
+    }
+
+    #[test]
+    fn records_failure_if_classifier_fails() {
+        let recorder = Arc::new(ClassifierErrorRecorder::default());
+        let mut cmd = TestCommand::<TestError, ()>::define(Config::default(), Err).unwrap();
+        cmd.classify_failure(classify_internal_as_success)
+            .add_observer(recorder.clone());
+
+        assert_eq!(Err(TestError::External), cmd.run(TestError::External));
+        assert_eq!(1.0, cmd.error_rate());
+        assert_eq!(
+            vec![ClassifierError::new("unknown error")],
+            *recorder.errors.lock().unwrap()
+        );
+    }
+
+    #[test]
+    fn records_failure_if_classifier_panics() {
+        let recorder = Arc::new(ClassifierErrorRecorder::default());
+        let mut cmd = TestCommand::<TestError, ()>::define(Config::default(), Err).unwrap();
+        cmd.classify_failure(|_| panic!("unparseable status"))
+            .add_observer(recorder.clone());
+
+        assert_eq!(Err(TestError::Internal), cmd.run(TestError::Internal));
+        assert_eq!(1.0, cmd.error_rate());
+        assert_eq!(1, recorder.errors.lock().unwrap().len());
+    }
+
+    #[test]
+    fn exposes_breaker() {
+        let config = *Config::default().error_threshold(2);
+        let mut cmd = TestCommand::<(), u8>::define(config, |_| Ok(5)).unwrap();
+        assert_eq!(CircuitState::Closed, cmd.breaker().state());
+
+        // Outcomes recorded through the breaker open the circuit:
+        let breaker = cmd.breaker_mut();
+        assert!(breaker.check_command_allowed());
+        breaker.register_result(&Err::<(), ()>(()));
+        breaker.register_result(&Err::<(), ()>(()));
+        assert!(!breaker.check_command_allowed());
+
+        assert_eq!(CircuitState::Open, cmd.breaker().state());
+        assert_eq!(Err(TestError::External), cmd.run(()));
+
+        cmd.breaker_mut().reset();
+        assert_eq!(Ok(5), cmd.run(()));
+    }
+
+    #[test]
+    fn mirrors_calls_to_secondary() {
+        let mut primary = TestCommand::<u8, u8>::define(Config::default(), Ok).unwrap();
+        let mut secondary = TestCommand::<u8, u8>::define(Config::default(), |param| {
+            if param % 2 == 0 {
+                Err(TestError::Internal)
+            } else {
+                Ok(param * 2)
+            }
+        })
+        .unwrap();
+
+        assert_eq!(Ok(1), primary.run_mirrored(1, &mut secondary));
+        assert_eq!(Ok(2), primary.run_mirrored(2, &mut secondary));
+        assert_eq!(Ok(4), primary.run_mirrored(4, &mut secondary));
+
+        assert_eq!(0.0, primary.error_rate());
+        assert_eq!(2.0 / 3.0, secondary.error_rate());
+        assert_eq!(3, primary.metrics().success_nr);
+        assert_eq!(1, secondary.metrics().success_nr);
+    }
+
+    #[test]
+    fn reports_window_coverage() {
+        let clock = Arc::new(ManualClock::new());
+        let mut cmd = TestCommand::<(), u8>::define(Config::default(), |_| Ok(5)).unwrap();
+        cmd.clock(clock.clone());
+        assert_eq!(0, cmd.window_coverage().total_points);
+
+        cmd.run(()).unwrap();
+        clock.advance(time::Duration::from_millis(1500));
+        cmd.run(()).unwrap();
+
+        let coverage = cmd.window_coverage();
+        assert_eq!(2, coverage.total_points);
+        assert_eq!(time::Duration::from_millis(1500), coverage.span);
+        assert_eq!(coverage.total_points as i32, cmd.metrics().success_nr);
+    }
 }
@@ -0,0 +1,18 @@
+//! Fixtures shared by the integration tests. Not every test uses all
+//! of them.
+#![allow(dead_code)]
+
+use crius::command::Command;
+use crius::error::CriusError;
+
+pub type TestCommand =
+    Command<bool, (), CriusError, fn(bool) -> Result<(), CriusError>, fn(CriusError)>;
+
+/// Command function succeeding for `true` and failing for `false`.
+pub fn succeed_if(ok: bool) -> Result<(), CriusError> {
+    if ok {
+        Ok(())
+    } else {
+        Err(CriusError::InvalidInput)
+    }
+}
@@ -0,0 +1,121 @@
+extern crate crius;
+
+mod config {
+    use crius::command::{Command, Config, ConfigProblem, ConfigWarning, LatencyGuard};
+    use crius::error::CriusError;
+    use std::time::Duration;
+
+    type TestCommand =
+        Command<(), (), CriusError, fn(()) -> Result<(), CriusError>, fn(CriusError)>;
+
+    #[test]
+    fn default_config_has_no_warnings() {
+        assert!(Config::default().validate().unwrap().is_empty());
+    }
+
+    #[test]
+    fn warns_about_window_shorter_than_open_duration() {
+        let config = *Config::default()
+            .buckets_in_window(5)
+            .bucket_size_in_ms(100)
+            .circuit_open_ms(1000);
+
+        assert_eq!(
+            vec![ConfigWarning::WindowShorterThanOpenDuration],
+            config.validate().unwrap()
+        );
+        let cmd = TestCommand::define(config, |_| Ok(())).unwrap();
+        assert_eq!(
+            &[ConfigWarning::WindowShorterThanOpenDuration],
+            cmd.config_warnings()
+        );
+    }
+
+    #[test]
+    fn rejects_window_shorter_than_open_duration_if_flagged() {
+        let config = *Config::default()
+            .buckets_in_window(5)
+            .bucket_size_in_ms(100)
+            .circuit_open_ms(1000)
+            .short_window_is_error(true);
+
+        match config.validate() {
+            Err(CriusError::InvalidConfig) => (),
+            other => panic!("Expected invalid config, got {:?}", other),
+        }
+        assert!(TestCommand::define(config, |_| Ok(())).is_err());
+    }
+
+    #[test]
+    fn rejects_overflowing_window() {
+        let config = *Config::default()
+            .buckets_in_window(u32::MAX)
+            .bucket_size_in_ms(u64::MAX);
+
+        assert!(config.validate().is_err());
+        assert!(TestCommand::define(config, |_| Ok(())).is_err());
+    }
+
+    #[test]
+    fn rejects_latency_guard_percentile_out_of_range() {
+        for &p in &[0.0, 1.5] {
+            let config = *Config::default().latency_trend_guard(Some(LatencyGuard {
+                p,
+                threshold_ms: 100,
+            }));
+
+            match config.validate() {
+                Err(CriusError::InvalidConfig) => (),
+                other => panic!("Expected invalid config, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_thresholds_out_of_range() {
+        let configs = [
+            *Config::default().error_threshold(-1),
+            *Config::default().error_threshold_percentage(-1),
+            *Config::default().error_threshold_percentage(101),
+        ];
+
+        for config in &configs {
+            match config.validate() {
+                Err(CriusError::InvalidConfig) => (),
+                other => panic!("Expected invalid config, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_recovery_ramp_fraction_out_of_range() {
+        const RAMP: &[(f64, Duration)] = &[(1.5, Duration::from_secs(1))];
+        let config = *Config::default().recovery_ramp(Some(RAMP));
+
+        match config.validate() {
+            Err(CriusError::InvalidConfig) => (),
+            other => panic!("Expected invalid config, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn reports_all_problems() {
+        let config = *Config::default()
+            .error_threshold_percentage(150)
+            .buckets_in_window(5)
+            .bucket_size_in_ms(100)
+            .circuit_open_ms(1000)
+            .short_window_is_error(true)
+            .max_total_calls(Some(0));
+
+        assert_eq!(
+            Err(vec![
+                ConfigProblem::ErrorThresholdPercentageOutOfRange,
+                ConfigProblem::ZeroMaxTotalCalls,
+                ConfigProblem::WindowShorterThanOpenDuration,
+            ]),
+            config.validate_all()
+        );
+        assert!(config.validate().is_err());
+    }
+}
@@ -0,0 +1,51 @@
+extern crate crius;
+
+mod error {
+    use crius::error::CriusError;
+    use std::error::Error;
+
+    #[derive(PartialEq, Debug)]
+    enum TestError {
+        Rejected,
+        InvalidInput,
+        Other,
+    }
+
+    impl From<CriusError> for TestError {
+        fn from(err: CriusError) -> Self {
+            match err {
+                CriusError::ExecutionRejected => TestError::Rejected,
+                CriusError::InvalidInput => TestError::InvalidInput,
+                _ => TestError::Other,
+            }
+        }
+    }
+
+    #[test]
+    fn displays_invalid_input() {
+        let display = CriusError::InvalidInput.to_string();
+        assert_eq!("Rejected command execution due to invalid input", display);
+        assert_ne!(CriusError::ExecutionRejected.to_string(), display);
+        assert_ne!(CriusError::InvalidConfig.to_string(), display);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn describes_invalid_input() {
+        let err = CriusError::InvalidInput;
+        assert_eq!(err.to_string(), err.description());
+    }
+
+    #[test]
+    fn converts_into_user_error() {
+        assert_eq!(
+            TestError::InvalidInput,
+            TestError::from(CriusError::InvalidInput)
+        );
+        assert_eq!(
+            TestError::Rejected,
+            TestError::from(CriusError::ExecutionRejected)
+        );
+        assert_eq!(TestError::Other, TestError::from(CriusError::InvalidConfig));
+    }
+}
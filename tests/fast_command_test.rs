@@ -0,0 +1,108 @@
+extern crate crius;
+
+mod common;
+
+mod fast_command {
+    use common::succeed_if;
+    use crius::clock::ManualClock;
+    use crius::command::{BreakerMode, Config};
+    use crius::error::CriusError;
+    use crius::fast_command::FastCommand;
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+    use std::sync::Arc;
+    use std::time;
+
+    /// Allocator counting the allocations made by the current thread,
+    /// so allocations of concurrently running tests are not counted.
+    struct CountingAllocator;
+
+    thread_local! {
+        static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let _ = ALLOCATIONS.try_with(|allocations| allocations.set(allocations.get() + 1));
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    fn allocations() -> usize {
+        ALLOCATIONS.with(|allocations| allocations.get())
+    }
+
+    type TestCommand =
+        FastCommand<bool, (), CriusError, fn(bool) -> Result<(), CriusError>, fn(CriusError)>;
+
+    #[test]
+    fn runs_without_allocating() {
+        let mut cmd =
+            TestCommand::define_with_fallback(Config::default(), succeed_if, |_| ()).unwrap();
+
+        let before = allocations();
+        for i in 0..10_000 {
+            cmd.run(i % 3 != 0).unwrap();
+        }
+
+        assert_eq!(before, allocations());
+    }
+
+    #[test]
+    fn opens_circuit_on_errors() {
+        let config = *Config::default().error_threshold(3);
+        let mut cmd = TestCommand::define(config, succeed_if).unwrap();
+
+        for _ in 0..3 {
+            assert!(cmd.run(false).is_err());
+        }
+        assert!(!cmd.is_open());
+
+        match cmd.run(true) {
+            Err(CriusError::ExecutionRejected) => (),
+            other => panic!("Expected rejection, got {:?}", other),
+        }
+        assert!(cmd.is_open());
+    }
+
+    #[test]
+    fn closes_circuit_after_open_duration() {
+        let clock = Arc::new(ManualClock::new());
+        let config = *Config::default().error_threshold(1).circuit_open_ms(1000);
+        let mut cmd = TestCommand::define(config, succeed_if).unwrap();
+        cmd.clock(clock.clone());
+
+        assert!(cmd.run(false).is_err());
+        assert!(cmd.run(true).is_err());
+        assert!(cmd.is_open());
+
+        clock.advance(time::Duration::from_millis(1000));
+        assert!(cmd.run(true).is_ok());
+        assert!(!cmd.is_open());
+    }
+
+    #[test]
+    fn rejects_unsupported_options() {
+        let configs = [
+            *Config::default().mode(BreakerMode::ConsecutiveFailures(3)),
+            *Config::default().latch_open(true),
+            *Config::default().warmup_ms(Some(1000)),
+            *Config::default().circuit_open_ms(5),
+        ];
+
+        for config in &configs {
+            match TestCommand::define(*config, succeed_if) {
+                Err(CriusError::InvalidConfig) => (),
+                Err(err) => panic!("Expected invalid config, got {:?}", err),
+                Ok(_) => panic!("Expected invalid config for {:?}", config),
+            }
+        }
+    }
+}
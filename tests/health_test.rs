@@ -0,0 +1,78 @@
+extern crate crius;
+
+mod common;
+
+mod health {
+    use common::{succeed_if, TestCommand};
+    use crius::circuit_breaker::CircuitState;
+    use crius::command::Config;
+    use crius::health::{self, BreakerHealth, HealthReport};
+
+    fn define(name: &str, critical: bool) -> TestCommand {
+        let config = *Config::default().error_threshold(1);
+        let mut cmd = TestCommand::define(config, succeed_if).unwrap();
+        cmd.add_observer(health::register(name, critical));
+        cmd
+    }
+
+    fn state_of(report: &HealthReport, name: &str) -> CircuitState {
+        report
+            .breakers
+            .iter()
+            .find(|breaker| breaker.name == name)
+            .unwrap()
+            .state
+    }
+
+    #[test]
+    fn turns_unhealthy_while_critical_breaker_is_open() {
+        let mut cache = define("cache", false);
+        let mut database = define("database", true);
+        assert!(health::report_all().healthy);
+
+        // The circuit opens on the call following the failure:
+        let _ = cache.run(false);
+        let _ = cache.run(true);
+        let report = health::report_all();
+        assert_eq!(CircuitState::Open, state_of(&report, "cache"));
+        assert!(report.healthy);
+
+        let _ = database.run(false);
+        let _ = database.run(true);
+        let report = health::report_all();
+        assert_eq!(CircuitState::Open, state_of(&report, "database"));
+        assert!(!report.healthy);
+
+        // Dropping the command unregisters its breaker:
+        drop(database);
+        let report = health::report_all();
+        assert!(report.healthy);
+        assert_eq!(1, report.breakers.len());
+    }
+
+    #[test]
+    fn serializes_report_as_json() {
+        let report = HealthReport {
+            healthy: false,
+            breakers: vec![
+                BreakerHealth {
+                    name: "data\"base".to_string(),
+                    critical: true,
+                    state: CircuitState::Open,
+                },
+                BreakerHealth {
+                    name: "cache".to_string(),
+                    critical: false,
+                    state: CircuitState::Closed,
+                },
+            ],
+        };
+
+        assert_eq!(
+            "{\"healthy\":false,\"breakers\":[\
+             {\"name\":\"data\\\"base\",\"critical\":true,\"state\":\"open\"},\
+             {\"name\":\"cache\",\"critical\":false,\"state\":\"closed\"}]}",
+            report.to_json()
+        );
+    }
+}
@@ -0,0 +1,84 @@
+extern crate crius;
+
+mod latency_histogram {
+    use crius::clock::ManualClock;
+    use crius::command::{Command, Config};
+    use crius::error::CriusError;
+    use crius::latency_histogram::LatencyHistogram;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn histogram_of(latencies_us: &[u64]) -> LatencyHistogram {
+        let mut histogram = LatencyHistogram::new();
+        for &latency in latencies_us {
+            histogram.record(Duration::from_micros(latency));
+        }
+        histogram
+    }
+
+    fn count_below(histogram: &LatencyHistogram, upper_bound_us: u64) -> u64 {
+        histogram
+            .buckets()
+            .find(|&(upper_bound, _)| upper_bound == Some(Duration::from_micros(upper_bound_us)))
+            .map(|(_, count)| count)
+            .unwrap()
+    }
+
+    #[test]
+    fn counts_latencies_in_exponential_buckets() {
+        let histogram = histogram_of(&[0, 1, 2, 3, 4, 1000, 1023, 1024]);
+
+        assert_eq!(1, count_below(&histogram, 1));
+        assert_eq!(1, count_below(&histogram, 2));
+        assert_eq!(2, count_below(&histogram, 4));
+        assert_eq!(1, count_below(&histogram, 8));
+        assert_eq!(2, count_below(&histogram, 1024));
+        assert_eq!(1, count_below(&histogram, 2048));
+        assert_eq!(8, histogram.count());
+    }
+
+    #[test]
+    fn counts_huge_latencies_in_last_bucket() {
+        let histogram = histogram_of(&[u64::MAX]);
+        let (upper_bound, count) = histogram.buckets().last().unwrap();
+
+        assert_eq!(None, upper_bound);
+        assert_eq!(1, count);
+    }
+
+    #[test]
+    fn merges_histograms() {
+        let mut first = histogram_of(&[3, 1000]);
+        let second = histogram_of(&[2, 5, 1000]);
+        first.merge(&second);
+
+        assert_eq!(histogram_of(&[3, 1000, 2, 5, 1000]), first);
+        assert_eq!(2, count_below(&first, 4));
+        assert_eq!(2, count_below(&first, 1024));
+    }
+
+    #[test]
+    fn records_latencies_of_command() {
+        let clock = Arc::new(ManualClock::new());
+        let call_clock = clock.clone();
+        let mut cmd = Command::<u64, (), CriusError, _, fn(CriusError)>::define(
+            Config::default(),
+            move |latency_ms| {
+                call_clock.advance(Duration::from_millis(latency_ms));
+                Ok(())
+            },
+        )
+        .unwrap();
+        cmd.clock(clock);
+
+        for &latency_ms in &[1, 3, 3, 100] {
+            cmd.run(latency_ms).unwrap();
+        }
+
+        let histogram = cmd.latency_histogram();
+        assert_eq!(4, histogram.count());
+        assert_eq!(1, count_below(&histogram, 1024));
+        assert_eq!(2, count_below(&histogram, 4096));
+        assert_eq!(1, count_below(&histogram, 131_072));
+    }
+}
@@ -0,0 +1,154 @@
+#![cfg(feature = "metrics")]
+
+extern crate crius;
+extern crate metrics;
+
+mod common;
+
+mod metrics_facade {
+    use common::{succeed_if, TestCommand};
+    use crius::command::Config;
+    use metrics::{
+        Counter, CounterFn, Gauge, GaugeFn, Histogram, HistogramFn, Key, KeyName, Metadata,
+        Recorder, SharedString, Unit,
+    };
+    use std::sync::{Arc, Mutex};
+
+    /// A metric logging every value it was incremented by, set to or
+    /// recorded.
+    struct Metric {
+        name: String,
+        breaker: String,
+        values: Mutex<Vec<f64>>,
+    }
+
+    impl Metric {
+        fn push(&self, value: f64) {
+            self.values.lock().unwrap().push(value);
+        }
+    }
+
+    impl CounterFn for Metric {
+        fn increment(&self, value: u64) {
+            self.push(value as f64);
+        }
+
+        fn absolute(&self, value: u64) {
+            self.push(value as f64);
+        }
+    }
+
+    impl GaugeFn for Metric {
+        fn increment(&self, value: f64) {
+            self.push(value);
+        }
+
+        fn decrement(&self, value: f64) {
+            self.push(-value);
+        }
+
+        fn set(&self, value: f64) {
+            self.push(value);
+        }
+    }
+
+    impl HistogramFn for Metric {
+        fn record(&self, value: f64) {
+            self.push(value);
+        }
+    }
+
+    /// A recorder keeping one `Metric` per name and breaker label.
+    #[derive(Default)]
+    struct TestRecorder {
+        metrics: Mutex<Vec<Arc<Metric>>>,
+    }
+
+    impl TestRecorder {
+        fn metric(&self, key: &Key) -> Arc<Metric> {
+            let breaker = key
+                .labels()
+                .find(|label| label.key() == "breaker")
+                .map(|label| label.value().to_string())
+                .unwrap_or_default();
+            let mut metrics = self.metrics.lock().unwrap();
+            if let Some(metric) = metrics
+                .iter()
+                .find(|metric| metric.name == key.name() && metric.breaker == breaker)
+            {
+                return metric.clone();
+            }
+            let metric = Arc::new(Metric {
+                name: key.name().to_string(),
+                breaker,
+                values: Mutex::new(Vec::new()),
+            });
+            metrics.push(metric.clone());
+            metric
+        }
+
+        /// Returns the values logged by the metric with the given name.
+        fn values_of(&self, name: &str) -> Vec<f64> {
+            self.metrics
+                .lock()
+                .unwrap()
+                .iter()
+                .find(|metric| metric.name == name)
+                .expect("Expected metric to be recorded")
+                .values
+                .lock()
+                .unwrap()
+                .clone()
+        }
+    }
+
+    impl Recorder for TestRecorder {
+        fn describe_counter(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+        fn describe_gauge(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+        fn describe_histogram(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+        fn register_counter(&self, key: &Key, _: &Metadata<'_>) -> Counter {
+            Counter::from_arc(self.metric(key))
+        }
+
+        fn register_gauge(&self, key: &Key, _: &Metadata<'_>) -> Gauge {
+            Gauge::from_arc(self.metric(key))
+        }
+
+        fn register_histogram(&self, key: &Key, _: &Metadata<'_>) -> Histogram {
+            Histogram::from_arc(self.metric(key))
+        }
+    }
+
+    #[test]
+    fn records_outcomes_through_facade() {
+        let recorder = TestRecorder::default();
+
+        metrics::with_local_recorder(&recorder, || {
+            let config = *Config::default().error_threshold(2);
+            let mut cmd = TestCommand::define(config, succeed_if).unwrap();
+            cmd.emit_metrics("payments");
+
+            cmd.run(true).unwrap();
+            cmd.run(false).unwrap_err();
+            cmd.run(false).unwrap_err();
+            cmd.run(true).unwrap_err();
+            cmd.run(true).unwrap_err();
+        });
+
+        assert!(recorder
+            .metrics
+            .lock()
+            .unwrap()
+            .iter()
+            .all(|metric| metric.breaker == "payments"));
+        let sum = |name| recorder.values_of(name).iter().sum::<f64>();
+        assert_eq!(1.0, sum("crius.successes"));
+        assert_eq!(2.0, sum("crius.failures"));
+        assert_eq!(2.0, sum("crius.rejections"));
+        assert_eq!(Some(&1.0), recorder.values_of("crius.state").last());
+        assert_eq!(3, recorder.values_of("crius.latency").len());
+    }
+}
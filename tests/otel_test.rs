@@ -0,0 +1,76 @@
+#![cfg(feature = "opentelemetry")]
+
+extern crate crius;
+extern crate opentelemetry;
+extern crate opentelemetry_sdk;
+
+mod common;
+
+mod otel {
+    use common::{succeed_if, TestCommand};
+    use crius::command::Config;
+    use opentelemetry::metrics::MeterProvider;
+    use opentelemetry::KeyValue;
+    use opentelemetry_sdk::metrics::data::{AggregatedMetrics, MetricData};
+    use opentelemetry_sdk::metrics::{InMemoryMetricExporter, PeriodicReader, SdkMeterProvider};
+
+    /// Returns the last exported value of the instrument with the
+    /// given name, checking it is attributed to the breaker:
+    fn value_of(exporter: &InMemoryMetricExporter, name: &str) -> u64 {
+        let resource_metrics = exporter.get_finished_metrics().unwrap();
+        let metric = resource_metrics
+            .last()
+            .expect("Expected exported metrics")
+            .scope_metrics()
+            .flat_map(|scope| scope.metrics())
+            .find(|metric| metric.name() == name)
+            .expect("Expected metric to be exported");
+
+        match *metric.data() {
+            AggregatedMetrics::U64(MetricData::Sum(ref sum)) => sum
+                .data_points()
+                .inspect(|point| assert_breaker(point.attributes()))
+                .map(|point| point.value())
+                .sum(),
+            AggregatedMetrics::U64(MetricData::Gauge(ref gauge)) => {
+                let point = gauge.data_points().last().unwrap();
+                assert_breaker(point.attributes());
+                point.value()
+            }
+            ref data => panic!("Unexpected metric data {:?}", data),
+        }
+    }
+
+    fn assert_breaker<'a>(attributes: impl Iterator<Item = &'a KeyValue>) {
+        let attributes: Vec<_> = attributes.collect();
+        assert_eq!(vec![&KeyValue::new("breaker", "payments")], attributes);
+    }
+
+    #[test]
+    fn records_outcomes_as_instruments() {
+        let exporter = InMemoryMetricExporter::default();
+        let provider = SdkMeterProvider::builder()
+            .with_reader(PeriodicReader::builder(exporter.clone()).build())
+            .build();
+
+        let config = *Config::default().error_threshold(2);
+        let mut cmd = TestCommand::define(config, succeed_if).unwrap();
+        cmd.bind_meter(&provider.meter("crius"), "payments");
+
+        cmd.run(true).unwrap();
+        provider.force_flush().unwrap();
+        assert_eq!(1, value_of(&exporter, "crius.successes"));
+        assert_eq!(0, value_of(&exporter, "crius.state"));
+
+        cmd.run(false).unwrap_err();
+        cmd.run(false).unwrap_err();
+        cmd.run(true).unwrap_err();
+        cmd.run(true).unwrap_err();
+        provider.force_flush().unwrap();
+
+        assert_eq!(1, value_of(&exporter, "crius.successes"));
+        assert_eq!(2, value_of(&exporter, "crius.failures"));
+        assert_eq!(2, value_of(&exporter, "crius.rejections"));
+        assert_eq!(1, value_of(&exporter, "crius.state"));
+    }
+}
@@ -0,0 +1,87 @@
+#![cfg(feature = "prometheus")]
+
+extern crate crius;
+extern crate prometheus;
+
+mod common;
+
+mod prometheus_metrics {
+    use common::{succeed_if, TestCommand};
+    use crius::command::Config;
+    use prometheus::{IntGauge, Opts, Registry};
+
+    /// Returns the value of the metric family with the given name:
+    fn value_of(registry: &Registry, name: &str) -> f64 {
+        let family = registry
+            .gather()
+            .into_iter()
+            .find(|family| family.name() == name)
+            .expect("Expected metric family to be registered");
+        let metric = &family.get_metric()[0];
+        assert_eq!("breaker", metric.get_label()[0].name());
+        assert_eq!("payments", metric.get_label()[0].value());
+
+        if name.ends_with("_total") {
+            metric.get_counter().get_value()
+        } else {
+            metric.get_gauge().get_value()
+        }
+    }
+
+    #[test]
+    fn registers_metric_families() {
+        let registry = Registry::new();
+        let config = *Config::default().error_threshold(2);
+        let mut cmd = TestCommand::define(config, succeed_if).unwrap();
+        cmd.register_metrics(&registry, "payments").unwrap();
+
+        cmd.run(true).unwrap();
+        cmd.run(false).unwrap_err();
+        cmd.run(false).unwrap_err();
+        cmd.run(true).unwrap_err();
+
+        assert_eq!(1.0, value_of(&registry, "crius_successes_total"));
+        assert_eq!(2.0, value_of(&registry, "crius_failures_total"));
+        assert_eq!(1.0, value_of(&registry, "crius_rejections_total"));
+        assert_eq!(1.0, value_of(&registry, "crius_state"));
+    }
+
+    #[test]
+    fn records_state_at_registration() {
+        let registry = Registry::new();
+        let config = *Config::default().error_threshold(1);
+        let mut cmd = TestCommand::define(config, succeed_if).unwrap();
+        cmd.run(false).unwrap_err();
+        cmd.run(true).unwrap_err();
+
+        cmd.register_metrics(&registry, "payments").unwrap();
+
+        assert_eq!(1.0, value_of(&registry, "crius_state"));
+    }
+
+    #[test]
+    fn rejects_duplicate_breaker_names() {
+        let registry = Registry::new();
+        let mut first = TestCommand::define(Config::default(), succeed_if).unwrap();
+        let mut second = TestCommand::define(Config::default(), succeed_if).unwrap();
+
+        assert!(first.register_metrics(&registry, "payments").is_ok());
+        assert!(second.register_metrics(&registry, "payments").is_err());
+    }
+
+    #[test]
+    fn unregisters_collectors_if_registration_fails() {
+        let registry = Registry::new();
+        let help = "State of the breaker, 0 if closed and 1 if open";
+        let opts = Opts::new("crius_state", help).const_label("breaker", "payments");
+        let taken = IntGauge::with_opts(opts).unwrap();
+        registry.register(Box::new(taken.clone())).unwrap();
+
+        let mut cmd = TestCommand::define(Config::default(), succeed_if).unwrap();
+        assert!(cmd.register_metrics(&registry, "payments").is_err());
+        assert_eq!(1, registry.gather().len());
+
+        registry.unregister(Box::new(taken)).unwrap();
+        assert!(cmd.register_metrics(&registry, "payments").is_ok());
+    }
+}
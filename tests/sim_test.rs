@@ -0,0 +1,65 @@
+#![cfg(feature = "sim")]
+
+extern crate crius;
+
+mod sim {
+    use crius::circuit_breaker::CircuitState;
+    use crius::command::Config;
+    use crius::sim::{SimulatedDependency, Simulation};
+    use std::time::Duration;
+
+    fn outage(start: u64, end: u64) -> SimulatedDependency {
+        let mut dependency = SimulatedDependency::new();
+        dependency
+            .phase(Duration::from_secs(start), 0.0, Duration::from_millis(10))
+            .phase(
+                Duration::from_secs(end - start),
+                1.0,
+                Duration::from_millis(10),
+            )
+            .phase(Duration::from_secs(60), 0.0, Duration::from_millis(10));
+        dependency
+    }
+
+    #[test]
+    fn follows_programmed_phases() {
+        let dependency = outage(10, 20);
+        let error_rate_at = |secs| {
+            let phase = dependency.phase_at(Duration::from_secs(secs));
+            phase.unwrap().error_rate
+        };
+
+        assert_eq!(0.0, error_rate_at(5));
+        assert_eq!(1.0, error_rate_at(10));
+        assert_eq!(0.0, error_rate_at(500));
+
+        let no_phases = SimulatedDependency::new();
+        assert!(no_phases.phase_at(Duration::from_secs(1)).is_none());
+    }
+
+    #[test]
+    fn breaker_opens_during_outage_and_recovers_after_it() {
+        let report = Simulation::new(outage(10, 30))
+            .run(Config::default(), Duration::from_secs(60))
+            .unwrap();
+
+        assert_eq!(
+            CircuitState::Closed,
+            report.state_at(Duration::from_secs(9))
+        );
+        assert!(report.opened_between(Duration::from_secs(10), Duration::from_secs(30)));
+        assert!(report.rejections > 0);
+
+        let recovered_at = report.closed_since().unwrap();
+        assert!(recovered_at > Duration::from_secs(30));
+        assert!(recovered_at <= Duration::from_secs(35));
+    }
+
+    #[test]
+    fn rejects_invalid_config() {
+        let config = *Config::default().error_threshold_percentage(200);
+        assert!(Simulation::new(outage(1, 2))
+            .run(config, Duration::from_secs(1))
+            .is_err());
+    }
+}
@@ -0,0 +1,290 @@
+extern crate crius;
+
+mod window {
+    use crius::clock::{Clock, ManualClock};
+    use crius::command::Config;
+    use crius::window::{Point, Window};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+
+    fn window_with_clock(clock: &Arc<ManualClock>) -> Window {
+        let config = *Config::default()
+            .buckets_in_window(10)
+            .bucket_size_in_ms(1000);
+        Window::with_clock(config, clock.clone()).unwrap()
+    }
+
+    #[test]
+    fn expires_points_with_manual_clock() {
+        let clock = Arc::new(ManualClock::new());
+        let mut window = window_with_clock(&clock);
+        window.add_point(Point::FAILURE);
+
+        clock.advance(Duration::from_millis(9_999));
+        assert_eq!(1, window.get_points().len());
+
+        clock.advance(Duration::from_millis(1));
+        assert!(window.get_points().is_empty());
+        assert!(window.is_empty());
+    }
+
+    #[test]
+    fn ignores_future_buckets_after_clock_went_backwards() {
+        let clock = Arc::new(ManualClock::new());
+        let mut window = window_with_clock(&clock);
+        window.add_point(Point::FAILURE);
+        window.add_point(Point::FAILURE);
+
+        clock.rewind(Duration::from_secs(5));
+        assert!(window.get_points().is_empty());
+        assert_eq!(0.0, window.get_failure_score());
+        assert!(window.is_empty());
+
+        window.add_point(Point::SUCCESS);
+        assert_eq!(vec![Point::SUCCESS], window.get_points());
+
+        // The points recorded before the jump were dropped when the
+        // window was updated and do not reappear:
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(vec![Point::SUCCESS], window.get_points());
+    }
+
+    #[test]
+    fn handles_clock_before_window_size() {
+        let clock = Arc::new(ManualClock::new());
+        let mut window = window_with_clock(&clock);
+
+        // Move the clock as far back as possible, so the start of the
+        // window can not be represented:
+        let mut step = Duration::from_secs(1 << 40);
+        while step > Duration::from_millis(1) {
+            if clock.now().checked_sub(step).is_some() {
+                clock.rewind(step);
+            } else {
+                step /= 2;
+            }
+        }
+        window.add_point(Point::FAILURE);
+        assert_eq!(vec![Point::FAILURE], window.get_points());
+        assert!(!window.is_empty());
+    }
+
+    #[test]
+    fn snapshots_share_point_storage() {
+        let clock = Arc::new(ManualClock::new());
+        let mut window = window_with_clock(&clock);
+        for bucket in 0..10 {
+            for i in 0..10_000 {
+                window.add_point(if i % 4 == 0 {
+                    Point::FAILURE
+                } else {
+                    Point::SUCCESS
+                });
+            }
+            if bucket < 9 {
+                clock.advance(Duration::from_millis(1000));
+            }
+        }
+
+        let snapshot = window.snapshot();
+        assert_eq!(75_000, snapshot.success_nr());
+        assert_eq!(25_000, snapshot.error_nr());
+        assert_eq!(25_000.0, snapshot.failure_score());
+    }
+
+    #[test]
+    fn snapshots_are_not_affected_by_later_points() {
+        let clock = Arc::new(ManualClock::new());
+        let mut window = window_with_clock(&clock);
+        window.add_point(Point::SUCCESS);
+
+        let snapshot = window.snapshot();
+        window.add_point(Point::FAILURE);
+
+        assert_eq!(vec![Point::SUCCESS], snapshot.points().collect::<Vec<_>>());
+        assert_eq!(2, window.get_points().len());
+    }
+
+    #[test]
+    fn snapshots_only_contain_valid_buckets() {
+        let clock = Arc::new(ManualClock::new());
+        let mut window = window_with_clock(&clock);
+        window.add_point(Point::FAILURE);
+        clock.advance(Duration::from_secs(10));
+
+        assert_eq!(0, window.snapshot().points().count());
+    }
+
+    #[test]
+    fn starts_bucket_at_current_period_after_pause() {
+        let clock = Arc::new(ManualClock::new());
+        let mut window = window_with_clock(&clock);
+        window.add_point(Point::SUCCESS);
+
+        clock.advance(Duration::from_millis(20_500));
+        window.add_point(Point::FAILURE);
+        window.add_point(Point::FAILURE);
+
+        // Both points belong to the bucket starting at 20s, which
+        // expires at 30s:
+        clock.advance(Duration::from_millis(9_499));
+        assert_eq!(vec![Point::FAILURE, Point::FAILURE], window.get_points());
+
+        clock.advance(Duration::from_millis(1));
+        assert!(window.is_empty());
+    }
+
+    #[test]
+    fn single_bucket_counts_points_until_it_expires() {
+        let clock = Arc::new(ManualClock::new());
+        let config = *Config::default()
+            .buckets_in_window(1)
+            .bucket_size_in_ms(1000);
+        let mut window = Window::with_clock(config, clock.clone()).unwrap();
+
+        window.add_point(Point::FAILURE);
+        clock.advance(Duration::from_millis(999));
+        window.add_point(Point::SUCCESS);
+        assert_eq!(vec![Point::FAILURE, Point::SUCCESS], window.get_points());
+
+        // Crossing the bucket boundary replaces the only bucket:
+        clock.advance(Duration::from_millis(1));
+        assert!(window.is_empty());
+        window.add_point(Point::FAILURE);
+        assert_eq!(vec![Point::FAILURE], window.get_points());
+        assert_eq!(1.0, window.get_failure_score());
+
+        clock.advance(Duration::from_millis(999));
+        assert_eq!(vec![Point::FAILURE], window.get_points());
+
+        clock.advance(Duration::from_millis(1));
+        assert!(window.is_empty());
+        assert_eq!(0.0, window.get_failure_score());
+    }
+
+    #[test]
+    fn single_bucket_keeps_period_after_pause() {
+        let clock = Arc::new(ManualClock::new());
+        let config = *Config::default()
+            .buckets_in_window(1)
+            .bucket_size_in_ms(1000);
+        let mut window = Window::with_clock(config, clock.clone()).unwrap();
+        window.add_point(Point::SUCCESS);
+
+        // The point belongs to the bucket starting at 5s:
+        clock.advance(Duration::from_millis(5_700));
+        window.add_point(Point::FAILURE);
+        assert_eq!(vec![Point::FAILURE], window.get_points());
+
+        clock.advance(Duration::from_millis(299));
+        window.add_point(Point::FAILURE);
+        assert_eq!(2, window.get_points().len());
+
+        clock.advance(Duration::from_millis(1));
+        window.add_point(Point::SUCCESS);
+        assert_eq!(vec![Point::SUCCESS], window.get_points());
+    }
+
+    #[test]
+    fn restores_exported_buckets() {
+        let clock = Arc::new(ManualClock::new());
+        let mut window = window_with_clock(&clock);
+        window.add_point(Point::FAILURE);
+        clock.advance(Duration::from_millis(2_500));
+        window.add_weighted_point(Point::FAILURE, 2.0);
+        window.add_point(Point::SUCCESS);
+        clock.advance(Duration::from_millis(1_000));
+        window.add_point(Point::SUCCESS);
+
+        let export = window.export();
+        assert_eq!(3, export.buckets.len());
+        assert_eq!(Duration::from_millis(3_500), export.buckets[0].age);
+
+        let other_clock = Arc::new(ManualClock::new());
+        other_clock.advance(Duration::from_secs(100));
+        let config = *Config::default()
+            .buckets_in_window(10)
+            .bucket_size_in_ms(1000);
+        let mut restored = Window::from_export(config, other_clock.clone(), &export).unwrap();
+        assert_eq!(export, restored.export());
+        assert_eq!(window.get_points(), restored.get_points());
+        assert_eq!(window.get_failure_score(), restored.get_failure_score());
+        assert_eq!(
+            window.get_recent_failure_score(2),
+            restored.get_recent_failure_score(2)
+        );
+
+        // Buckets expire relative to the time of the export:
+        other_clock.advance(Duration::from_millis(6_500));
+        assert_eq!(3, restored.get_points().len());
+        assert_eq!(2.0, restored.get_failure_score());
+    }
+
+    #[test]
+    fn sequences_points_across_threads() {
+        let clock = Arc::new(ManualClock::new());
+        let mut window = window_with_clock(&clock);
+        window.add_point(Point::SUCCESS);
+        assert_eq!(None, window.bucket_summaries());
+
+        // Points recorded before sequences were enabled are left out:
+        window.record_sequences();
+        let window = Arc::new(Mutex::new(window));
+
+        let threads: Vec<_> = (0..8)
+            .map(|i| {
+                let window = window.clone();
+                thread::spawn(move || {
+                    for _ in 0..100 {
+                        let point = if i % 2 == 0 {
+                            Point::SUCCESS
+                        } else {
+                            Point::FAILURE
+                        };
+                        window.lock().unwrap().add_point(point);
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        let summaries = window.lock().unwrap().bucket_summaries().unwrap();
+        let sequences: Vec<u64> = summaries
+            .iter()
+            .flat_map(|summary| summary.points.iter().map(|&(sequence, _)| sequence))
+            .collect();
+        assert_eq!(800, sequences.len());
+        assert!(sequences.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn reports_coverage_of_valid_buckets() {
+        let clock = Arc::new(ManualClock::new());
+        let mut window = window_with_clock(&clock);
+        let coverage = window.coverage();
+        assert_eq!(None, coverage.oldest);
+        assert_eq!(0, coverage.total_points);
+        assert_eq!(Duration::from_millis(0), coverage.span);
+
+        let start = clock.now();
+        window.add_point(Point::FAILURE);
+        clock.advance(Duration::from_millis(2_500));
+        window.add_point(Point::SUCCESS);
+        window.add_point(Point::SUCCESS);
+
+        let coverage = window.coverage();
+        assert_eq!(Some(start), coverage.oldest);
+        assert_eq!(3, coverage.total_points);
+        assert_eq!(Duration::from_millis(2_500), coverage.span);
+
+        // The first bucket expires:
+        clock.advance(Duration::from_millis(7_500));
+        let coverage = window.coverage();
+        assert_eq!(Some(start + Duration::from_secs(2)), coverage.oldest);
+        assert_eq!(2, coverage.total_points);
+        assert_eq!(Duration::from_secs(8), coverage.span);
+    }
+}